@@ -0,0 +1,424 @@
+//! Access log lines written on request completion, as JSON or Apache Combined Log Format,
+//! to stdout and/or a rotating file.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, Method, Version, header},
+    middleware::Next,
+    response::Response,
+};
+use hyper::body::{Frame, SizeHint};
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{self, Write},
+    net::IpAddr,
+    path::PathBuf,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use time::OffsetDateTime;
+
+use crate::forwarded::ClientAddr;
+use crate::log_file::{LogFile, Rotation};
+use crate::routes::find_route;
+use crate::state::AppState;
+
+/// How requests are logged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Colored, human-oriented lines as requests progress
+    #[default]
+    Pretty,
+    /// One JSON object per completed request on stdout
+    Json,
+    /// One Apache Combined Log Format line per completed request on stdout
+    Combined,
+}
+
+impl LogFormat {
+    /// Whether completed requests get an access log line on stdout
+    ///
+    /// In those formats the regular log goes to stderr, keeping stdout
+    /// machine-readable.
+    pub fn is_access_log(self) -> bool {
+        self != LogFormat::Pretty
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LogFormat::Pretty => "pretty",
+            LogFormat::Json => "json",
+            LogFormat::Combined => "combined",
+        })
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.trim() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            "combined" => Ok(LogFormat::Combined),
+            _ => Err(format!(
+                "unknown log format '{}' (expected json, pretty or combined)",
+                format
+            )),
+        }
+    }
+}
+
+/// Rotated access log files kept when `--access-log-keep` is not set
+pub const DEFAULT_ACCESS_LOG_KEEP: usize = 7;
+
+/// Where and how the access log file is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessLogConfig {
+    pub path: PathBuf,
+    /// Json or combined; pretty is console-only
+    pub format: LogFormat,
+    pub rotation: Rotation,
+    /// Rotated files kept besides the live one
+    pub keep: usize,
+}
+
+impl AccessLogConfig {
+    /// Opens (or creates) the log file for appending
+    pub fn open(&self) -> io::Result<AccessLogFile> {
+        Ok(AccessLogFile {
+            format: self.format,
+            file: LogFile::open(&self.path, self.rotation, self.keep)?,
+        })
+    }
+}
+
+impl fmt::Display for AccessLogConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} ({}, rotate {}, keep {})",
+            self.path, self.format, self.rotation, self.keep
+        )
+    }
+}
+
+/// An open access log file, shared by every request
+#[derive(Debug, Clone)]
+pub struct AccessLogFile {
+    pub format: LogFormat,
+    pub file: LogFile,
+}
+
+/// Where a proxied request went, attached to its response for the access log
+#[derive(Debug, Clone)]
+pub struct UpstreamRequest {
+    pub url: String,
+    /// Time until the upstream's response headers arrived, if they did
+    pub latency: Option<Duration>,
+}
+
+/// One completed request, as logged in JSON mode
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessRecord {
+    /// Completion time, RFC 3339 in UTC
+    pub time: String,
+    pub id: String,
+    pub method: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    /// Route label, or `STATIC` for static files
+    pub route: String,
+    pub upstream: Option<String>,
+    pub status: u16,
+    /// Response body bytes sent
+    pub bytes: u64,
+    /// Total time until the response body was sent
+    pub latency_ms: u128,
+    /// Time until the upstream's response headers arrived
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_latency_ms: Option<u128>,
+    pub client: Option<IpAddr>,
+    #[serde(skip)]
+    completed: Option<OffsetDateTime>,
+    #[serde(skip)]
+    request_line: String,
+    #[serde(skip)]
+    referer: Option<String>,
+    #[serde(skip)]
+    user_agent: Option<String>,
+}
+
+impl AccessRecord {
+    /// Renders the record as a single line in `format`
+    pub fn line(&self, format: LogFormat) -> String {
+        match format {
+            LogFormat::Combined => self.combined(),
+            _ => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+
+    /// `host ident user [time] "request" status bytes "referer" "user-agent"`
+    fn combined(&self) -> String {
+        let quoted = |value: &Option<String>| match value {
+            Some(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+            None => "\"-\"".to_string(),
+        };
+        format!(
+            "{} - - [{}] \"{}\" {} {} {} {}",
+            self.client
+                .map_or_else(|| "-".to_string(), |ip| ip.to_string()),
+            self.completed.map(clf_time).unwrap_or_default(),
+            self.request_line.replace('"', "\\\""),
+            self.status,
+            if self.bytes == 0 {
+                "-".to_string()
+            } else {
+                self.bytes.to_string()
+            },
+            quoted(&self.referer),
+            quoted(&self.user_agent),
+        )
+    }
+}
+
+/// Middleware writing an access log line once each response body is sent
+pub async fn access_log(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let console = state.log_format.is_access_log().then_some(state.log_format);
+    let file = state.access_log_file.clone();
+    if console.is_none() && file.is_none() {
+        return next.run(request).await;
+    }
+
+    let start = request
+        .extensions()
+        .get::<Instant>()
+        .copied()
+        .unwrap_or_else(Instant::now);
+    let uri = request.uri();
+    let route = find_route(&state.routes, uri.path())
+        .map_or_else(|| "STATIC".to_string(), |route| route.label.clone());
+    let mut record = AccessRecord {
+        time: String::new(),
+        id: request
+            .extensions()
+            .get::<String>()
+            .cloned()
+            .unwrap_or_default(),
+        method: request.method().to_string(),
+        path: uri.path().to_string(),
+        query: uri.query().map(str::to_string),
+        route,
+        upstream: None,
+        status: 0,
+        bytes: 0,
+        latency_ms: 0,
+        upstream_latency_ms: None,
+        client: request
+            .extensions()
+            .get::<ConnectInfo<ClientAddr>>()
+            .map(|ConnectInfo(ClientAddr(addr))| addr.ip()),
+        completed: None,
+        request_line: request_line(request.method(), request.uri(), request.version()),
+        referer: header_value(request.headers(), header::REFERER),
+        user_agent: header_value(request.headers(), header::USER_AGENT),
+    };
+
+    let mut response = next.run(request).await;
+    record.status = response.status().as_u16();
+    if let Some(upstream) = response.extensions_mut().remove::<UpstreamRequest>() {
+        record.upstream = Some(upstream.url);
+        record.upstream_latency_ms = upstream.latency.map(|latency| latency.as_millis());
+    }
+
+    response.map(|body| {
+        Body::new(LoggedBody {
+            body,
+            record: Some(record),
+            console,
+            file,
+            start,
+        })
+    })
+}
+
+/// A response body that writes its access log line once it is dropped
+struct LoggedBody {
+    body: Body,
+    record: Option<AccessRecord>,
+    /// Format of the stdout line, if one is written
+    console: Option<LogFormat>,
+    file: Option<AccessLogFile>,
+    start: Instant,
+}
+
+impl HttpBody for LoggedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+            && let Some(record) = &mut self.record
+        {
+            record.bytes += data.len() as u64;
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some(mut record) = self.record.take() {
+            let now = OffsetDateTime::now_utc();
+            record.time = rfc3339(now);
+            record.completed = Some(now);
+            record.latency_ms = self.start.elapsed().as_millis();
+            if let Some(format) = self.console {
+                let _ = writeln!(io::stdout().lock(), "{}", record.line(format));
+            }
+            if let Some(log) = &self.file {
+                log.file.write_line(record.line(log.format));
+            }
+        }
+    }
+}
+
+/// The request line as sent by the client, e.g. `GET /app.js?v=2 HTTP/1.1`
+fn request_line(method: &Method, uri: &axum::http::Uri, version: Version) -> String {
+    let target = uri
+        .path_and_query()
+        .map_or_else(|| uri.path(), |target| target.as_str());
+    format!("{} {} {:?}", method, target, version)
+}
+
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// `2026-10-17T09:30:05.123Z`
+fn rfc3339(time: OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+        time.millisecond()
+    )
+}
+
+/// `17/Oct/2026:09:30:05 +0000`, the Common Log Format timestamp
+fn clf_time(time: OffsetDateTime) -> String {
+    format!(
+        "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+        time.day(),
+        &time.month().to_string()[..3],
+        time.year(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> AccessRecord {
+        let completed = OffsetDateTime::from_unix_timestamp(1_791_538_205).unwrap();
+        AccessRecord {
+            time: rfc3339(completed),
+            id: "aB3x_".to_string(),
+            method: "GET".to_string(),
+            path: "/pz/users".to_string(),
+            query: Some("page=2".to_string()),
+            route: "API".to_string(),
+            upstream: Some("http://127.0.0.1:8081/pz/users?page=2".to_string()),
+            status: 200,
+            bytes: 512,
+            latency_ms: 14,
+            upstream_latency_ms: Some(12),
+            client: Some("127.0.0.1".parse().unwrap()),
+            completed: Some(completed),
+            request_line: "GET /pz/users?page=2 HTTP/1.1".to_string(),
+            referer: Some("http://localhost:8000/".to_string()),
+            user_agent: Some("curl/8.5.0 \"test\"".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_json_line() {
+        let json: serde_json::Value =
+            serde_json::from_str(&record().line(LogFormat::Json)).unwrap();
+        assert_eq!(json["time"], "2026-10-09T09:30:05.000Z");
+        assert_eq!(json["id"], "aB3x_");
+        assert_eq!(json["route"], "API");
+        assert_eq!(json["upstream"], "http://127.0.0.1:8081/pz/users?page=2");
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["bytes"], 512);
+        assert_eq!(json["upstream_latency_ms"], 12);
+        assert_eq!(json["client"], "127.0.0.1");
+        assert!(json.get("request_line").is_none());
+    }
+
+    #[test]
+    fn test_combined_line() {
+        assert_eq!(
+            record().line(LogFormat::Combined),
+            "127.0.0.1 - - [09/Oct/2026:09:30:05 +0000] \"GET /pz/users?page=2 HTTP/1.1\" \
+             200 512 \"http://localhost:8000/\" \"curl/8.5.0 \\\"test\\\"\""
+        );
+
+        let mut record = record();
+        record.client = None;
+        record.bytes = 0;
+        record.referer = None;
+        assert!(
+            record
+                .line(LogFormat::Combined)
+                .starts_with("- - - [09/Oct/2026:09:30:05 +0000]")
+        );
+        assert!(record.line(LogFormat::Combined).contains(" 200 - \"-\" "));
+    }
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!(
+            "combined".parse::<LogFormat>().unwrap(),
+            LogFormat::Combined
+        );
+        assert_eq!("pretty".parse::<LogFormat>().unwrap(), LogFormat::Pretty);
+        assert!("logfmt".parse::<LogFormat>().is_err());
+    }
+}
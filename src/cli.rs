@@ -1,24 +1,150 @@
 //! Command-line interface configuration.
 
 use argh::FromArgs;
+use axum::http::HeaderName;
 use std::{net::SocketAddr, path::PathBuf};
 
+use crate::access_log::LogFormat;
+use crate::encoding::Encoding;
+use crate::forwarded::TrustMode;
+use crate::log_file::Rotation;
+use crate::middleware::RequestIdHeader;
+use crate::rewrite::RewriteRule;
+use crate::routes::ProxyRoute;
+
 /// A high-performance reverse proxy server
 #[derive(Debug, FromArgs)]
 pub struct Cli {
+    /// TOML config file (default: './local-rs.toml' when present)
+    #[argh(option)]
+    pub config: Option<PathBuf>,
+
     /// path to static files directory (e.g. 'dist/')
     #[argh(option, long = "static-dir")]
-    pub static_dir: PathBuf,
+    pub static_dir: Option<PathBuf>,
 
     /// backend API address (e.g. '127.0.0.1:8081')
     #[argh(option)]
-    pub api: String,
+    pub api: Option<String>,
 
     /// API path prefix (default: '/pz')
-    #[argh(option, long = "api-path", default = "String::from(\"/pz\")")]
-    pub api_path: String,
+    #[argh(option, long = "api-path")]
+    pub api_path: Option<String>,
+
+    /// additional proxy route, repeatable: PREFIX=UPSTREAM[,strip][,label=NAME]
+    /// [,preserve-host][,host-override=HOST][,connect-timeout=SECS]
+    /// [,first-byte-timeout=SECS][,timeout=SECS]; timeouts are whole seconds
+    /// (default: 10 to connect, 60 to first byte, none in total), 0 disables
+    /// one (e.g. '/auth=127.0.0.1:9000,strip,timeout=30')
+    #[argh(option, long = "route")]
+    pub routes: Vec<ProxyRoute>,
+
+    /// upstream path rewrite applied to every route, repeatable:
+    /// strip:PREFIX, replace:FROM=>TO or regex:PATTERN=>REPLACEMENT
+    #[argh(option, long = "rewrite")]
+    pub rewrites: Vec<RewriteRule>,
+
+    /// serve the fallback document for unmatched client-side routes
+    #[argh(switch)]
+    pub spa: bool,
+
+    /// SPA fallback document, relative to the static dir (default: 'index.html')
+    #[argh(option, long = "spa-fallback")]
+    pub spa_fallback: Option<PathBuf>,
+
+    /// reload the browser when files in the static dir change
+    #[argh(switch, long = "live-reload")]
+    pub live_reload: bool,
+
+    /// expose Prometheus metrics at /__local/metrics
+    #[argh(switch)]
+    pub metrics: bool,
+
+    /// compress static and proxied responses on the fly
+    #[argh(switch)]
+    pub compress: bool,
+
+    /// coding offered by --compress, repeatable: br, zstd or gzip (default: all)
+    #[argh(option, long = "compress-algorithm")]
+    pub compress_algorithms: Vec<Encoding>,
+
+    /// smallest response body in bytes that --compress compresses (default: 1024)
+    #[argh(option, long = "compress-min-size")]
+    pub compress_min_size: Option<u64>,
+
+    /// content type prefix --compress applies to, repeatable (default: text/,
+    /// JavaScript, JSON, XML, SVG and WebAssembly)
+    #[argh(option, long = "compress-type")]
+    pub compress_types: Vec<String>,
+
+    /// header never passed between client and backend, in either direction,
+    /// on top of the standard hop-by-hop set, repeatable
+    #[argh(option, long = "strip-header")]
+    pub strip_headers: Vec<HeaderName>,
+
+    /// also send a standard RFC 7239 Forwarded header to backends
+    #[argh(switch)]
+    pub forwarded: bool,
+
+    /// incoming X-Forwarded-*/Forwarded headers: replace (default) or append
+    /// when behind another proxy
+    #[argh(option, long = "forwarded-trust")]
+    pub forwarded_trust: Option<TrustMode>,
+
+    /// largest request body proxied to backends, in bytes; larger uploads get
+    /// 413 (default: unlimited)
+    #[argh(option, long = "max-body-size")]
+    pub max_body_size: Option<u64>,
+
+    /// PEM certificate chain to serve HTTPS with (requires --tls-key)
+    #[argh(option, long = "tls-cert")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[argh(option, long = "tls-key")]
+    pub tls_key: Option<PathBuf>,
+
+    /// serve HTTPS with a certificate issued by a cached local CA
+    #[argh(switch, long = "tls-self-signed")]
+    pub tls_self_signed: bool,
+
+    /// seconds in-flight requests get to finish on shutdown (default: 10)
+    #[argh(option, long = "drain-timeout")]
+    pub drain_timeout: Option<u64>,
+
+    /// request log format: pretty (default), json or combined; the latter two
+    /// write one line per completed request to stdout
+    #[argh(option, long = "log-format")]
+    pub log_format: Option<LogFormat>,
+
+    /// header request IDs are reused from, forwarded to backends in and
+    /// returned in (default: X-Request-Id)
+    #[argh(option, long = "request-id-header")]
+    pub request_id_header: Option<RequestIdHeader>,
+
+    /// OTLP/HTTP collector to export request and upstream spans to, e.g.
+    /// http://127.0.0.1:4318; enables W3C traceparent propagation
+    #[argh(option, long = "otlp-endpoint")]
+    pub otlp_endpoint: Option<String>,
+
+    /// also write an access log line per completed request to this file
+    #[argh(option, long = "access-log")]
+    pub access_log: Option<PathBuf>,
+
+    /// access log file format: combined (default) or json
+    #[argh(option, long = "access-log-format")]
+    pub access_log_format: Option<LogFormat>,
+
+    /// when to rotate the access log file: never (default), daily, or a size
+    /// such as 100M
+    #[argh(option, long = "access-log-rotate")]
+    pub access_log_rotate: Option<Rotation>,
+
+    /// rotated access log files to keep (default: 7)
+    #[argh(option, long = "access-log-keep")]
+    pub access_log_keep: Option<usize>,
 
     /// server bind address (default: '127.0.0.1:8000')
-    #[argh(option, default = "\"127.0.0.1:8000\".parse().unwrap()")]
-    pub bind: SocketAddr,
+    #[argh(option)]
+    pub bind: Option<SocketAddr>,
 }
@@ -90,17 +90,21 @@ mod tests {
             color_discriminants.insert(format!("{:?}", std::mem::discriminant(&color)));
         }
         // With 32 colors and 100 random-ish IDs, we expect to see many distinct colors
-        assert!(color_discriminants.len() > 5, "Color distribution is poor: only {} colors used", color_discriminants.len());
+        assert!(
+            color_discriminants.len() > 5,
+            "Color distribution is poor: only {} colors used",
+            color_discriminants.len()
+        );
     }
 
     #[test]
     fn test_colored_id_format() {
         let id = "test-id";
         let result = colored_id(id);
-        
+
         // Should contain the ID wrapped in brackets
         assert!(result.contains("[test-id]"));
-        
+
         // Should contain ANSI escape codes (starts with \x1b[)
         assert!(result.contains("\x1b["));
     }
@@ -110,7 +114,7 @@ mod tests {
         // The simple hash function: acc.wrapping_mul(31).wrapping_add(c as u32)
         // For "A" (65): 0 * 31 + 65 = 65. 65 % 32 = 1. COLORS[1] = Green
         assert_eq!(get_color_for_id("A"), COLORS[1]);
-        
+
         // For "AA": (65 * 31 + 65) = 2080. 2080 % 32 = 0. COLORS[0] = Red
         assert_eq!(get_color_for_id("AA"), COLORS[0]);
     }
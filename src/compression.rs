@@ -0,0 +1,209 @@
+//! On-the-fly response compression for static and proxied responses.
+
+use axum::{
+    body::HttpBody,
+    http::{HeaderValue, Response, header},
+};
+use tower_http::compression::{CompressionLayer, Predicate};
+
+use crate::encoding::Encoding;
+
+/// Responses smaller than this are not worth compressing by default
+pub const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// MIME types compressed when no allowlist is configured
+pub const DEFAULT_MIME_TYPES: &[&str] = &[
+    "text/",
+    "application/javascript",
+    "application/json",
+    "application/manifest+json",
+    "application/wasm",
+    "application/xml",
+    "image/svg+xml",
+];
+
+/// Which responses get compressed, and with which codings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Codings offered to clients; tower-http picks by the client's preference
+    pub algorithms: Vec<Encoding>,
+    /// Minimum body size in bytes; responses of unknown size are compressed
+    pub min_size: u64,
+    /// `Content-Type` prefixes eligible for compression (e.g. "text/")
+    pub mime_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            algorithms: Encoding::PREFERENCE.to_vec(),
+            min_size: DEFAULT_MIN_SIZE,
+            mime_types: DEFAULT_MIME_TYPES.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Builds a config from CLI values, keeping the defaults for empty lists
+    pub fn new(algorithms: Vec<Encoding>, min_size: u64, mime_types: Vec<String>) -> Self {
+        let defaults = CompressionConfig::default();
+        CompressionConfig {
+            algorithms: if algorithms.is_empty() {
+                defaults.algorithms
+            } else {
+                algorithms
+            },
+            min_size,
+            mime_types: if mime_types.is_empty() {
+                defaults.mime_types
+            } else {
+                mime_types
+            },
+        }
+    }
+
+    /// The tower-http layer applying this config
+    ///
+    /// Responses that already carry a `Content-Encoding` (precompressed
+    /// assets, backends that compress themselves) or a `Content-Range` are
+    /// left untouched by tower-http.
+    pub fn layer(&self) -> CompressionLayer<CompressionPolicy> {
+        let enabled = |encoding| self.algorithms.contains(&encoding);
+        CompressionLayer::new()
+            .br(enabled(Encoding::Brotli))
+            .zstd(enabled(Encoding::Zstd))
+            .gzip(enabled(Encoding::Gzip))
+            .compress_when(CompressionPolicy {
+                min_size: self.min_size,
+                mime_types: self.mime_types.clone(),
+            })
+    }
+}
+
+/// Marks responses that reach the compression layer already encoded
+#[derive(Debug, Clone, Copy)]
+struct PreEncoded;
+
+/// Runs inside the compression layer, tagging precompressed and
+/// backend-encoded responses so [`weaken_compressed_etag`] leaves them alone
+pub async fn mark_pre_encoded<B>(mut response: Response<B>) -> Response<B> {
+    if response.headers().contains_key(header::CONTENT_ENCODING) {
+        response.extensions_mut().insert(PreEncoded);
+    }
+    response
+}
+
+/// Runs outside the compression layer, weakening the strong `ETag` of every
+/// body it encoded
+///
+/// The encoded bytes differ from the identity body the tag was computed for,
+/// so a strong tag would claim byte equality it no longer has. The weak tag
+/// still revalidates with `If-None-Match`, which compares weakly.
+pub async fn weaken_compressed_etag<B>(mut response: Response<B>) -> Response<B> {
+    if response.extensions().get::<PreEncoded>().is_none()
+        && response.headers().contains_key(header::CONTENT_ENCODING)
+        && let Some(etag) = response.headers().get(header::ETAG)
+        && etag.as_bytes().starts_with(b"\"")
+    {
+        let mut weak = b"W/".to_vec();
+        weak.extend_from_slice(etag.as_bytes());
+        if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+            response.headers_mut().insert(header::ETAG, weak);
+        }
+    }
+    response
+}
+
+/// Size and MIME type checks deciding whether a response is compressed
+#[derive(Debug, Clone)]
+pub struct CompressionPolicy {
+    min_size: u64,
+    mime_types: Vec<String>,
+}
+
+impl CompressionPolicy {
+    /// Whether a `Content-Type` is on the allowlist
+    ///
+    /// Event streams never are: compressors buffer, which would hold back
+    /// live reload and other Server-Sent Events.
+    pub fn allows_content_type(&self, content_type: &str) -> bool {
+        let content_type = content_type.trim().to_ascii_lowercase();
+        !content_type.starts_with("text/event-stream")
+            && self
+                .mime_types
+                .iter()
+                .any(|allowed| content_type.starts_with(&allowed.to_ascii_lowercase()))
+    }
+}
+
+impl Predicate for CompressionPolicy {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        let size = response.body().size_hint().exact().or_else(|| {
+            response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+        });
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        size.is_none_or(|size| size >= self.min_size) && self.allows_content_type(content_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> CompressionPolicy {
+        let config = CompressionConfig::default();
+        CompressionPolicy {
+            min_size: config.min_size,
+            mime_types: config.mime_types,
+        }
+    }
+
+    fn response(content_type: &str, body: String) -> Response<String> {
+        Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_policy_checks_mime_allowlist() {
+        let policy = policy();
+        assert!(policy.allows_content_type("text/html; charset=utf-8"));
+        assert!(policy.allows_content_type("Application/JSON"));
+        assert!(!policy.allows_content_type("image/png"));
+        assert!(!policy.allows_content_type("text/event-stream"));
+        assert!(!policy.allows_content_type(""));
+    }
+
+    #[test]
+    fn test_policy_checks_min_size() {
+        let policy = policy();
+        let large = "x".repeat(2048);
+        assert!(policy.should_compress(&response("text/css", large.clone())));
+        assert!(!policy.should_compress(&response("text/css", "tiny".into())));
+        assert!(!policy.should_compress(&response("image/png", large)));
+    }
+
+    #[test]
+    fn test_config_new_keeps_defaults_for_empty_lists() {
+        let config = CompressionConfig::new(vec![], 10, vec![]);
+        assert_eq!(config.algorithms, Encoding::PREFERENCE);
+        assert_eq!(config.min_size, 10);
+        assert_eq!(config.mime_types, CompressionConfig::default().mime_types);
+
+        let config = CompressionConfig::new(vec![Encoding::Gzip], 0, vec!["text/".into()]);
+        assert_eq!(config.algorithms, [Encoding::Gzip]);
+        assert_eq!(config.mime_types, ["text/"]);
+    }
+}
@@ -0,0 +1,922 @@
+//! TOML configuration file, merged with command-line flags.
+//!
+//! Every key is optional and mirrors the long flag of the same name. Flags
+//! given on the command line override the file; lists given on the command
+//! line replace the file's, except routes, which override by prefix.
+
+use axum::http::{HeaderName, HeaderValue};
+use serde::{Deserialize, Deserializer, de};
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::access_log::{AccessLogConfig, AccessLogFile, DEFAULT_ACCESS_LOG_KEEP, LogFormat};
+use crate::cli::Cli;
+use crate::compression::{CompressionConfig, DEFAULT_MIN_SIZE};
+use crate::encoding::Encoding;
+use crate::forwarded::{ForwardedConfig, TrustMode};
+use crate::hop_by_hop::HopByHop;
+use crate::live_reload::LiveReload;
+use crate::log_file::Rotation;
+use crate::metrics::Metrics;
+use crate::middleware::RequestIdHeader;
+use crate::otlp::Exporter;
+use crate::rewrite::RewriteRule;
+use crate::routes::{ProxyRoute, default_label, normalize_prefix};
+use crate::shutdown::DEFAULT_DRAIN_TIMEOUT;
+use crate::state::AppState;
+use crate::tls::TlsFiles;
+use crate::upstream::{Clients, timeout_from_secs};
+
+/// Config file picked up from the working directory when `--config` is absent
+pub const DEFAULT_CONFIG_FILE: &str = "local-rs.toml";
+
+/// Address served on when neither `--bind` nor `bind` is set
+pub const DEFAULT_BIND: &str = "127.0.0.1:8000";
+
+/// API prefix used when neither `--api-path` nor `api-path` is set
+pub const DEFAULT_API_PATH: &str = "/pz";
+
+/// SPA fallback used when neither `--spa-fallback` nor `spa-fallback` is set
+pub const DEFAULT_SPA_FALLBACK: &str = "index.html";
+
+/// The contents of a config file, before flags are applied
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FileConfig {
+    pub static_dir: Option<PathBuf>,
+    pub bind: Option<SocketAddr>,
+    pub api: Option<String>,
+    pub api_path: Option<String>,
+    #[serde(default)]
+    pub routes: Vec<ProxyRoute>,
+    #[serde(default)]
+    pub rewrites: Vec<RewriteRule>,
+    pub spa: Option<bool>,
+    pub spa_fallback: Option<PathBuf>,
+    pub live_reload: Option<bool>,
+    pub metrics: Option<bool>,
+    #[serde(default)]
+    pub strip_headers: Vec<String>,
+    pub forwarded: Option<bool>,
+    pub forwarded_trust: Option<TrustMode>,
+    /// Largest request body proxied upstream, in bytes
+    pub max_body_size: Option<u64>,
+    /// Present as a `[compress]` table when compression is on
+    pub compress: Option<CompressFileConfig>,
+    pub tls: Option<TlsFileConfig>,
+    /// Seconds in-flight requests get to finish on shutdown
+    pub drain_timeout: Option<u64>,
+    pub log_format: Option<LogFormat>,
+    pub request_id_header: Option<RequestIdHeader>,
+    pub otlp_endpoint: Option<String>,
+    pub access_log: Option<PathBuf>,
+    pub access_log_format: Option<LogFormat>,
+    pub access_log_rotate: Option<Rotation>,
+    pub access_log_keep: Option<usize>,
+}
+
+/// The `[compress]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct CompressFileConfig {
+    #[serde(default)]
+    pub algorithms: Vec<Encoding>,
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+/// The `[tls]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TlsFileConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    #[serde(default)]
+    pub self_signed: bool,
+}
+
+/// A `[[routes]]` entry, the table form of `--route`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RouteTable {
+    prefix: String,
+    upstream: String,
+    label: Option<String>,
+    #[serde(default)]
+    strip: bool,
+    #[serde(default)]
+    rewrites: Vec<RewriteRule>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    preserve_host: bool,
+    host_override: Option<String>,
+    /// Seconds, `0` for none; the defaults apply when unset
+    connect_timeout: Option<u64>,
+    first_byte_timeout: Option<u64>,
+    timeout: Option<u64>,
+}
+
+impl FileConfig {
+    /// Reads and parses a config file
+    ///
+    /// Relative paths in the file are resolved against the file's directory,
+    /// so a config works the same from any working directory.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read config {:?}: {}", path, e))?;
+        let mut config = FileConfig::parse(&text).map_err(|e| format!("{:?}: {}", path, e))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let resolve = |path: &mut PathBuf| *path = base.join(&*path);
+        config.static_dir.iter_mut().for_each(resolve);
+        config.access_log.iter_mut().for_each(resolve);
+        if let Some(tls) = &mut config.tls {
+            tls.cert
+                .iter_mut()
+                .chain(tls.key.iter_mut())
+                .for_each(resolve);
+        }
+        Ok(config)
+    }
+
+    /// Parses config file contents; errors name the offending key and line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string().trim_end().to_string())
+    }
+}
+
+/// How HTTPS is served, if at all
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsMode {
+    /// A certificate issued by the cached local CA
+    SelfSigned,
+    /// A certificate and key from disk
+    Files(TlsFiles),
+}
+
+/// The effective configuration: file values overridden by flags
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Root directory for static file serving, as configured
+    pub static_dir: PathBuf,
+    /// Server bind address
+    pub bind: SocketAddr,
+    /// Proxy routes, with global rewrites already appended to each
+    pub routes: Vec<ProxyRoute>,
+    /// SPA fallback document relative to the static dir, when SPA mode is on
+    pub spa_fallback: Option<PathBuf>,
+    /// Whether browsers reload when static files change
+    pub live_reload: bool,
+    /// Whether Prometheus metrics are served
+    pub metrics: bool,
+    /// Response compression settings when compression is on
+    pub compression: Option<CompressionConfig>,
+    /// Headers stripped from proxied messages besides the standard hop-by-hop set
+    pub strip_headers: Vec<HeaderName>,
+    /// Forwarding headers added to proxied requests
+    pub forwarded: ForwardedConfig,
+    /// Largest request body proxied upstream, in bytes; unlimited when unset
+    pub max_body_size: Option<u64>,
+    /// HTTPS settings when TLS is on
+    pub tls: Option<TlsMode>,
+    /// How long in-flight requests get to finish on shutdown
+    pub drain_timeout: Duration,
+    /// How requests are logged
+    pub log_format: LogFormat,
+    /// Header request IDs are reused from, forwarded upstream in and returned in
+    pub request_id_header: RequestIdHeader,
+    /// OTLP/HTTP collector spans are exported to, when tracing is on
+    pub otlp_endpoint: Option<String>,
+    /// Access log file written besides the console, when set
+    pub access_log: Option<AccessLogConfig>,
+}
+
+/// The config file to load: `--config`, else `local-rs.toml` if present
+pub fn config_path(cli: &Cli) -> Option<PathBuf> {
+    cli.config.clone().or_else(|| {
+        let default = PathBuf::from(DEFAULT_CONFIG_FILE);
+        default.is_file().then_some(default)
+    })
+}
+
+impl Config {
+    /// Loads the config file (if any) and applies the command-line flags
+    pub fn load(cli: &Cli) -> Result<Self, String> {
+        let file = match config_path(cli) {
+            Some(path) => FileConfig::read(&path)?,
+            None => FileConfig::default(),
+        };
+        Config::merge(cli, file)
+    }
+
+    /// Applies command-line flags on top of file values and validates the result
+    pub fn merge(cli: &Cli, file: FileConfig) -> Result<Self, String> {
+        let static_dir =
+            cli.static_dir.clone().or(file.static_dir).ok_or(
+                "no static directory: pass --static-dir or set static-dir in the config file",
+            )?;
+        let bind = cli
+            .bind
+            .or(file.bind)
+            .unwrap_or_else(|| DEFAULT_BIND.parse().unwrap());
+
+        // The API route counts as a flag only when its upstream came from one
+        let api_path = cli
+            .api_path
+            .clone()
+            .or(file.api_path)
+            .unwrap_or_else(|| DEFAULT_API_PATH.to_string());
+        let api_route = |api: &str| ProxyRoute::new("API", &api_path, api);
+        let mut routes: Vec<ProxyRoute> = file.api.as_deref().map(api_route).into_iter().collect();
+        routes.extend(file.routes);
+        let overrides = cli.api.as_deref().map(api_route).into_iter();
+        for route in overrides.chain(cli.routes.iter().cloned()) {
+            routes.retain(|existing| existing.prefix != route.prefix);
+            routes.push(route);
+        }
+        for (i, route) in routes.iter().enumerate() {
+            if routes[..i].iter().any(|other| other.prefix == route.prefix) {
+                return Err(format!(
+                    "route prefix '{}' is configured twice",
+                    route.prefix
+                ));
+            }
+            reqwest::Url::parse(&route.upstream).map_err(|e| {
+                format!(
+                    "route '{}' has an invalid upstream '{}': {}",
+                    route.prefix, route.upstream, e
+                )
+            })?;
+        }
+
+        let rewrites = or_file(&cli.rewrites, file.rewrites);
+        for route in &mut routes {
+            route.rewrites.extend(rewrites.iter().cloned());
+        }
+
+        let strip_headers = if cli.strip_headers.is_empty() {
+            file.strip_headers
+                .iter()
+                .map(|name| {
+                    HeaderName::try_from(name)
+                        .map_err(|_| format!("invalid header name '{}' in strip-headers", name))
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            cli.strip_headers.clone()
+        };
+
+        let spa = cli.spa || file.spa.unwrap_or(false);
+        let spa_fallback = cli
+            .spa_fallback
+            .clone()
+            .or(file.spa_fallback)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SPA_FALLBACK));
+
+        let compression = match file.compress {
+            Some(file) => Some(file),
+            None if cli.compress => Some(CompressFileConfig::default()),
+            None => None,
+        }
+        .map(|file| {
+            CompressionConfig::new(
+                or_file(&cli.compress_algorithms, file.algorithms),
+                cli.compress_min_size
+                    .or(file.min_size)
+                    .unwrap_or(DEFAULT_MIN_SIZE),
+                or_file(&cli.compress_types, file.types),
+            )
+        });
+
+        // TLS flags replace the whole [tls] table rather than single keys
+        let flag_tls = TlsFileConfig {
+            cert: cli.tls_cert.clone(),
+            key: cli.tls_key.clone(),
+            self_signed: cli.tls_self_signed,
+        };
+        let tls = if flag_tls.cert.is_some() || flag_tls.key.is_some() || flag_tls.self_signed {
+            flag_tls
+        } else {
+            file.tls.unwrap_or_default()
+        };
+        let tls = match (tls.cert, tls.key) {
+            _ if tls.self_signed => Some(TlsMode::SelfSigned),
+            (Some(cert), Some(key)) => Some(TlsMode::Files(TlsFiles { cert, key })),
+            (None, None) => None,
+            _ => return Err("a TLS certificate and key must be given together".to_string()),
+        };
+
+        let otlp_endpoint = cli.otlp_endpoint.clone().or(file.otlp_endpoint);
+        if let Some(endpoint) = &otlp_endpoint {
+            reqwest::Url::parse(endpoint)
+                .map_err(|e| format!("invalid otlp-endpoint '{}': {}", endpoint, e))?;
+        }
+
+        let access_log = match cli.access_log.clone().or(file.access_log) {
+            Some(path) => {
+                let format = cli
+                    .access_log_format
+                    .or(file.access_log_format)
+                    .unwrap_or(LogFormat::Combined);
+                if !format.is_access_log() {
+                    return Err("access-log-format must be json or combined".to_string());
+                }
+                Some(AccessLogConfig {
+                    path,
+                    format,
+                    rotation: cli
+                        .access_log_rotate
+                        .or(file.access_log_rotate)
+                        .unwrap_or_default(),
+                    keep: cli
+                        .access_log_keep
+                        .or(file.access_log_keep)
+                        .unwrap_or(DEFAULT_ACCESS_LOG_KEEP),
+                })
+            }
+            None => None,
+        };
+
+        Ok(Config {
+            static_dir,
+            bind,
+            routes,
+            spa_fallback: spa.then_some(spa_fallback),
+            live_reload: cli.live_reload || file.live_reload.unwrap_or(false),
+            metrics: cli.metrics || file.metrics.unwrap_or(false),
+            compression,
+            strip_headers,
+            forwarded: ForwardedConfig {
+                trust: cli
+                    .forwarded_trust
+                    .or(file.forwarded_trust)
+                    .unwrap_or_default(),
+                rfc7239: cli.forwarded || file.forwarded.unwrap_or(false),
+            },
+            max_body_size: cli.max_body_size.or(file.max_body_size),
+            tls,
+            drain_timeout: cli
+                .drain_timeout
+                .or(file.drain_timeout)
+                .map_or(DEFAULT_DRAIN_TIMEOUT, Duration::from_secs),
+            log_format: cli.log_format.or(file.log_format).unwrap_or_default(),
+            request_id_header: cli
+                .request_id_header
+                .clone()
+                .or(file.request_id_header)
+                .unwrap_or_default(),
+            otlp_endpoint,
+            access_log,
+        })
+    }
+
+    /// Builds the shared state serving this config
+    ///
+    /// On reload, `current` is the state being replaced. Its access log
+    /// writer and span exporter are carried over when the path and endpoint
+    /// are unchanged, instead of starting a second writer thread on the same
+    /// file or a second export task for the same collector.
+    pub fn app_state(&self, current: Option<&AppState>) -> Result<AppState, String> {
+        let static_dir = self
+            .static_dir
+            .canonicalize()
+            .map_err(|e| format!("static directory {:?}: {}", self.static_dir, e))?;
+        let open_log = current.and_then(|state| state.access_log_file.as_ref());
+        let access_log_file = match (&self.access_log, open_log) {
+            (Some(log), Some(open)) if open.file.path() == log.path => {
+                open.file.reconfigure(log.rotation, log.keep);
+                Some(AccessLogFile {
+                    format: log.format,
+                    file: open.file.clone(),
+                })
+            }
+            (Some(log), _) => Some(
+                log.open()
+                    .map_err(|e| format!("access log {:?}: {}", log.path, e))?,
+            ),
+            (None, _) => None,
+        };
+        let tracer = match (&self.otlp_endpoint, current.and_then(|s| s.tracer.as_ref())) {
+            (Some(endpoint), Some(tracer)) if tracer.endpoint() == endpoint => Some(tracer.clone()),
+            (endpoint, _) => endpoint.as_deref().map(Exporter::new),
+        };
+
+        Ok(AppState {
+            routes: self.routes.clone(),
+            spa_fallback: self.spa_fallback.as_ref().map(|f| static_dir.join(f)),
+            static_dir,
+            compression: self.compression.clone(),
+            hop_by_hop: HopByHop::new(self.strip_headers.clone()),
+            forwarded: self.forwarded.clone(),
+            https: self.tls.is_some(),
+            max_body_size: self.max_body_size,
+            log_format: self.log_format,
+            access_log_file,
+            request_id_header: self.request_id_header.clone(),
+            tracer,
+            live_reload: self.live_reload.then(LiveReload::new),
+            metrics: self.metrics.then(Metrics::default),
+            clients: Clients::default(),
+            shutdown: CancellationToken::new(),
+        })
+    }
+
+    /// Describes what differs from `old`, one line per change
+    pub fn diff(&self, old: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+        let mut changed = |name: &str, old: String, new: String| {
+            if old != new {
+                changes.push(format!("{}: {} → {}", name, old, new));
+            }
+        };
+        changed(
+            "static-dir",
+            format!("{:?}", old.static_dir),
+            format!("{:?}", self.static_dir),
+        );
+        changed("bind", old.bind.to_string(), self.bind.to_string());
+        changed(
+            "spa-fallback",
+            format!("{:?}", old.spa_fallback),
+            format!("{:?}", self.spa_fallback),
+        );
+        changed(
+            "live-reload",
+            old.live_reload.to_string(),
+            self.live_reload.to_string(),
+        );
+        changed("metrics", old.metrics.to_string(), self.metrics.to_string());
+        changed(
+            "compress",
+            describe_compression(&old.compression),
+            describe_compression(&self.compression),
+        );
+        changed("tls", format!("{:?}", old.tls), format!("{:?}", self.tls));
+        changed(
+            "strip-headers",
+            format!("{:?}", old.strip_headers),
+            format!("{:?}", self.strip_headers),
+        );
+        changed(
+            "forwarded",
+            format!("{:?}", old.forwarded),
+            format!("{:?}", self.forwarded),
+        );
+        changed(
+            "max-body-size",
+            format!("{:?}", old.max_body_size),
+            format!("{:?}", self.max_body_size),
+        );
+        changed(
+            "drain-timeout",
+            format!("{:?}", old.drain_timeout),
+            format!("{:?}", self.drain_timeout),
+        );
+        changed(
+            "log-format",
+            old.log_format.to_string(),
+            self.log_format.to_string(),
+        );
+        changed(
+            "request-id-header",
+            old.request_id_header.to_string(),
+            self.request_id_header.to_string(),
+        );
+        changed(
+            "otlp-endpoint",
+            describe(&old.otlp_endpoint),
+            describe(&self.otlp_endpoint),
+        );
+        changed(
+            "access-log",
+            describe(&old.access_log),
+            describe(&self.access_log),
+        );
+
+        for route in &old.routes {
+            if !self.routes.iter().any(|new| new.prefix == route.prefix) {
+                changes.push(format!("- route {}", route));
+            }
+        }
+        for route in &self.routes {
+            match old.routes.iter().find(|old| old.prefix == route.prefix) {
+                None => changes.push(format!("+ route {}", route)),
+                Some(old) if old != route => changes.push(format!(
+                    "~ route {}: {}",
+                    route.prefix,
+                    route_changes(old, route)
+                )),
+                Some(_) => {}
+            }
+        }
+        changes
+    }
+}
+
+/// An optional setting for the reload diff
+fn describe(setting: &Option<impl ToString>) -> String {
+    setting
+        .as_ref()
+        .map_or_else(|| "off".to_string(), ToString::to_string)
+}
+
+/// Summarizes compression settings for the reload diff
+fn describe_compression(compression: &Option<CompressionConfig>) -> String {
+    match compression {
+        Some(c) => {
+            let algorithms: Vec<_> = c.algorithms.iter().map(|a| a.as_str()).collect();
+            let mut summary = format!("{} ≥ {}B", algorithms.join(", "), c.min_size);
+            if c.mime_types != CompressionConfig::default().mime_types {
+                summary = format!("{} for {}", summary, c.mime_types.join(" "));
+            }
+            summary
+        }
+        None => "off".to_string(),
+    }
+}
+
+/// Lists the fields that differ between two routes sharing a prefix
+fn route_changes(old: &ProxyRoute, new: &ProxyRoute) -> String {
+    let mut fields = Vec::new();
+    if old.upstream != new.upstream {
+        fields.push(format!("upstream {} → {}", old.upstream, new.upstream));
+    }
+    if old.label != new.label {
+        fields.push(format!("label {} → {}", old.label, new.label));
+    }
+    if old.strip_prefix != new.strip_prefix {
+        fields.push(format!("strip {} → {}", old.strip_prefix, new.strip_prefix));
+    }
+    if old.rewrites != new.rewrites {
+        fields.push("rewrites".to_string());
+    }
+    if old.preserve_host != new.preserve_host {
+        fields.push(format!(
+            "preserve-host {} → {}",
+            old.preserve_host, new.preserve_host
+        ));
+    }
+    if old.host_override != new.host_override {
+        fields.push(format!(
+            "host-override {:?} → {:?}",
+            old.host_override, new.host_override
+        ));
+    }
+    if old.headers != new.headers {
+        fields.push("headers".to_string());
+    }
+    if old.timeouts != new.timeouts {
+        fields.push("timeouts".to_string());
+    }
+    fields.join(", ")
+}
+
+/// Command-line list values when any were given, file values otherwise
+fn or_file<T: Clone>(cli: &[T], file: Vec<T>) -> Vec<T> {
+    if cli.is_empty() { file } else { cli.to_vec() }
+}
+
+impl TryFrom<RouteTable> for ProxyRoute {
+    type Error = String;
+
+    fn try_from(table: RouteTable) -> Result<Self, Self::Error> {
+        let prefix = normalize_prefix(&table.prefix);
+        if prefix.is_empty() {
+            return Err(format!(
+                "route '{}' must have a non-root prefix",
+                table.prefix
+            ));
+        }
+        if table.upstream.is_empty() {
+            return Err(format!("route '{}' has an empty upstream", prefix));
+        }
+
+        let mut route = ProxyRoute::new(
+            table.label.unwrap_or_else(|| default_label(&prefix)),
+            &prefix,
+            &table.upstream,
+        );
+        route.strip_prefix = table.strip;
+        route.rewrites = table.rewrites;
+        route.preserve_host = table.preserve_host;
+        if let Some(host) = table.host_override {
+            route.host_override = Some(
+                HeaderValue::try_from(&host)
+                    .map_err(|_| format!("invalid host-override '{}'", host))?,
+            );
+        }
+        if let Some(secs) = table.connect_timeout {
+            route.timeouts.connect = timeout_from_secs(secs);
+        }
+        if let Some(secs) = table.first_byte_timeout {
+            route.timeouts.first_byte = timeout_from_secs(secs);
+        }
+        if let Some(secs) = table.timeout {
+            route.timeouts.total = timeout_from_secs(secs);
+        }
+        for (name, value) in table.headers {
+            let name = HeaderName::try_from(&name)
+                .map_err(|_| format!("invalid header name '{}'", name))?;
+            let value = HeaderValue::try_from(&value)
+                .map_err(|_| format!("invalid value for header '{}'", name))?;
+            route.headers.push((name, value));
+        }
+        Ok(route)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProxyRoute {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        RouteTable::deserialize(deserializer)?
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Deserializes types from the same string syntax their flags use
+macro_rules! deserialize_from_str {
+    ($($ty:ty),*) => {$(
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(de::Error::custom)
+            }
+        }
+    )*};
+}
+
+deserialize_from_str!(
+    Encoding,
+    LogFormat,
+    RequestIdHeader,
+    RewriteRule,
+    Rotation,
+    TrustMode
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upstream::DEFAULT_CONNECT_TIMEOUT;
+    use argh::FromArgs;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::from_args(&["local-rs"], args).unwrap()
+    }
+
+    const EXAMPLE: &str = r#"
+        static-dir = "dist"
+        bind = "0.0.0.0:3000"
+        api = "127.0.0.1:8081"
+        rewrites = ["strip:/v1"]
+        spa = true
+        live-reload = true
+        max-body-size = 1048576
+        access-log = "logs/access.log"
+        access-log-rotate = "10M"
+
+        [[routes]]
+        prefix = "/auth/"
+        upstream = "127.0.0.1:9000"
+        strip = true
+        rewrites = ["replace:/login=>/v2/login"]
+        headers = { x-tenant = "dev" }
+        host-override = "auth.test"
+        first-byte-timeout = 0
+        timeout = 120
+
+        [compress]
+        algorithms = ["gzip"]
+        min-size = 256
+
+        [tls]
+        self-signed = true
+    "#;
+
+    #[test]
+    fn test_parse_full_file() {
+        let config = Config::merge(&cli(&[]), FileConfig::parse(EXAMPLE).unwrap()).unwrap();
+        assert_eq!(config.static_dir, PathBuf::from("dist"));
+        assert_eq!(config.bind, "0.0.0.0:3000".parse().unwrap());
+        assert_eq!(config.spa_fallback, Some(PathBuf::from("index.html")));
+        assert!(config.live_reload);
+        assert_eq!(config.max_body_size, Some(1048576));
+        assert_eq!(config.tls, Some(TlsMode::SelfSigned));
+        assert_eq!(
+            config.access_log,
+            Some(AccessLogConfig {
+                path: PathBuf::from("logs/access.log"),
+                format: LogFormat::Combined,
+                rotation: Rotation::Size(10 * 1024 * 1024),
+                keep: DEFAULT_ACCESS_LOG_KEEP,
+            })
+        );
+
+        let compression = config.compression.unwrap();
+        assert_eq!(compression.algorithms, [Encoding::Gzip]);
+        assert_eq!(compression.min_size, 256);
+        assert_eq!(
+            compression.mime_types,
+            CompressionConfig::default().mime_types
+        );
+
+        let [api, auth] = &config.routes[..] else {
+            panic!("expected two routes: {:?}", config.routes);
+        };
+        assert_eq!(api.prefix, "/pz");
+        assert_eq!(api.upstream, "http://127.0.0.1:8081");
+        assert_eq!(api.rewrites.len(), 1);
+        assert_eq!(auth.label, "AUTH");
+        assert_eq!(auth.prefix, "/auth");
+        assert!(auth.strip_prefix);
+        assert_eq!(auth.host_override.as_ref().unwrap(), "auth.test");
+        assert_eq!(auth.timeouts.connect, Some(DEFAULT_CONNECT_TIMEOUT));
+        assert_eq!(auth.timeouts.first_byte, None);
+        assert_eq!(auth.timeouts.total, Some(Duration::from_secs(120)));
+        assert_eq!(
+            auth.rewrites
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>(),
+            ["replace:/login=>/v2/login", "strip:/v1"]
+        );
+        assert_eq!(
+            auth.headers,
+            [(
+                HeaderName::from_static("x-tenant"),
+                HeaderValue::from_static("dev")
+            )]
+        );
+    }
+
+    #[test]
+    fn test_errors_point_at_offending_key() {
+        let error =
+            FileConfig::parse("static-dir = \"dist\"\nspa-fallbak = \"app.html\"").unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        assert!(error.contains("unknown field `spa-fallbak`"), "{}", error);
+
+        let error =
+            FileConfig::parse("[[routes]]\nprefix = \"/\"\nupstream = \"x:1\"").unwrap_err();
+        assert!(error.contains("non-root prefix"), "{}", error);
+
+        let error = FileConfig::parse("[compress]\nalgorithms = [\"deflate\"]").unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        assert!(error.contains("unknown encoding 'deflate'"), "{}", error);
+    }
+
+    #[test]
+    fn test_flags_override_file() {
+        let file = FileConfig::parse(EXAMPLE).unwrap();
+        let config = Config::merge(
+            &cli(&[
+                "--static-dir",
+                "public",
+                "--api",
+                "127.0.0.1:9999",
+                "--route",
+                "/auth=127.0.0.1:7000",
+                "--compress-min-size",
+                "0",
+                "--max-body-size",
+                "1024",
+                "--request-id-header",
+                "X-Correlation-Id",
+                "--tls-cert",
+                "cert.pem",
+                "--tls-key",
+                "key.pem",
+            ]),
+            file,
+        )
+        .unwrap();
+
+        assert_eq!(config.static_dir, PathBuf::from("public"));
+        assert_eq!(config.bind, "0.0.0.0:3000".parse().unwrap());
+        let upstreams: Vec<_> = config.routes.iter().map(|r| r.upstream.as_str()).collect();
+        assert_eq!(
+            upstreams,
+            ["http://127.0.0.1:9999", "http://127.0.0.1:7000"]
+        );
+        assert_eq!(config.compression.unwrap().min_size, 0);
+        assert_eq!(config.max_body_size, Some(1024));
+        assert_eq!(config.request_id_header.to_string(), "x-correlation-id");
+        assert_eq!(
+            config.tls,
+            Some(TlsMode::Files(TlsFiles {
+                cert: "cert.pem".into(),
+                key: "key.pem".into(),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_defaults_without_file() {
+        let config = Config::merge(&cli(&["--static-dir", "dist"]), FileConfig::default()).unwrap();
+        assert_eq!(config.bind, DEFAULT_BIND.parse().unwrap());
+        assert!(config.routes.is_empty());
+        assert_eq!(config.spa_fallback, None);
+        assert_eq!(config.compression, None);
+        assert_eq!(config.max_body_size, None);
+        assert_eq!(config.tls, None);
+        assert_eq!(config.access_log, None);
+        assert_eq!(config.request_id_header, RequestIdHeader::default());
+        assert_eq!(config.otlp_endpoint, None);
+
+        let error = Config::merge(&cli(&[]), FileConfig::default()).unwrap_err();
+        assert!(error.contains("--static-dir"), "{}", error);
+    }
+
+    #[test]
+    fn test_rejects_duplicate_prefixes_and_half_tls() {
+        let file = FileConfig::parse(
+            "static-dir = \"dist\"\napi = \"x:1\"\n[[routes]]\nprefix = \"/pz\"\nupstream = \"y:2\"",
+        )
+        .unwrap();
+        let error = Config::merge(&cli(&[]), file).unwrap_err();
+        assert_eq!(error, "route prefix '/pz' is configured twice");
+
+        let file = FileConfig::parse("static-dir = \"dist\"\n[tls]\nkey = \"key.pem\"").unwrap();
+        assert!(Config::merge(&cli(&[]), file).is_err());
+
+        let error = Config::merge(
+            &cli(&["--access-log", "a.log", "--access-log-format", "pretty"]),
+            FileConfig::parse(EXAMPLE).unwrap(),
+        )
+        .unwrap_err();
+        assert_eq!(error, "access-log-format must be json or combined");
+    }
+
+    #[test]
+    fn test_rejects_invalid_upstream() {
+        let error = Config::merge(
+            &cli(&["--static-dir", "dist", "--route", "/a=localhost:99999"]),
+            FileConfig::default(),
+        )
+        .unwrap_err();
+        assert!(error.contains("invalid upstream"), "{}", error);
+
+        let error = Config::merge(
+            &cli(&["--static-dir", "dist", "--otlp-endpoint", "127.0.0.1:4318"]),
+            FileConfig::default(),
+        )
+        .unwrap_err();
+        assert!(error.contains("invalid otlp-endpoint"), "{}", error);
+    }
+
+    #[test]
+    fn test_diff_lists_changes() {
+        let old = Config::merge(&cli(&[]), FileConfig::parse(EXAMPLE).unwrap()).unwrap();
+        assert!(old.diff(&old).is_empty());
+
+        let new = Config::merge(
+            &cli(&[
+                "--route",
+                "/auth=127.0.0.1:7000",
+                "--route",
+                "/files=127.0.0.1:9100",
+            ]),
+            FileConfig::parse(&EXAMPLE.replace("api = \"127.0.0.1:8081\"", "")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            new.diff(&old),
+            [
+                "- route /pz/* → http://127.0.0.1:8081/pz/",
+                "~ route /auth: upstream http://127.0.0.1:9000 → http://127.0.0.1:7000, \
+                 strip true → false, rewrites, host-override Some(\"auth.test\") → None, headers, \
+                 timeouts",
+                "+ route /files/* → http://127.0.0.1:9100/files/",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_resolves_paths_against_file() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/test_config_read");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("local.toml");
+        fs::write(
+            &path,
+            "static-dir = \"dist\"\n[tls]\ncert = \"c.pem\"\nkey = \"/k.pem\"",
+        )
+        .unwrap();
+
+        let file = FileConfig::read(&path).unwrap();
+        assert_eq!(file.static_dir, Some(dir.join("dist")));
+        let tls = file.tls.unwrap();
+        assert_eq!(tls.cert, Some(dir.join("c.pem")));
+        assert_eq!(tls.key, Some(PathBuf::from("/k.pem")));
+
+        assert!(FileConfig::read(&dir.join("missing.toml")).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Content-coding negotiation (RFC 9110 §12.5.3) for compressed responses.
+
+use axum::http::{HeaderMap, header};
+use std::{fmt, str::FromStr};
+
+/// A content coding local-rs can serve
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// All codings, most preferred first (best compression ratio for text assets)
+    pub const PREFERENCE: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    /// The `Content-Encoding` token
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// File extension build tools use for precompressed siblings (`app.js.br`)
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zst",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses a content-coding token (`br`, `zstd`, `gzip`)
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        Encoding::PREFERENCE
+            .into_iter()
+            .find(|encoding| encoding.as_str().eq_ignore_ascii_case(token.trim()))
+            .ok_or_else(|| format!("unknown encoding '{}' (expected br, zstd or gzip)", token))
+    }
+}
+
+/// Codings the client accepts, best first
+///
+/// Higher `q` values win; ties go to [`Encoding::PREFERENCE`]. Codings with
+/// `q=0` are excluded, and `*` stands in for codings not listed explicitly.
+pub fn accepted_encodings(headers: &HeaderMap) -> Vec<Encoding> {
+    let weights = quality_values(headers, header::ACCEPT_ENCODING);
+
+    let weight_of = |encoding: Encoding| {
+        weights
+            .iter()
+            .find(|(token, _)| token.eq_ignore_ascii_case(encoding.as_str()))
+            .or_else(|| weights.iter().find(|(token, _)| *token == "*"))
+            .map_or(0.0, |(_, q)| *q)
+    };
+
+    let mut accepted: Vec<(Encoding, f32)> = Encoding::PREFERENCE
+        .into_iter()
+        .map(|encoding| (encoding, weight_of(encoding)))
+        .filter(|(_, q)| *q > 0.0)
+        .collect();
+    // Stable, so equal weights keep the preference order
+    accepted.sort_by(|a, b| b.1.total_cmp(&a.1));
+    accepted.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+/// The items of an `Accept`-style header with their `q` weights, in order
+///
+/// Items without a `q` parameter weigh `1.0`; other parameters are ignored.
+pub fn quality_values(headers: &HeaderMap, name: header::HeaderName) -> Vec<(&str, f32)> {
+    let mut weights = Vec::new();
+    for value in headers.get_all(name) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        for item in value.split(',') {
+            let mut params = item.split(';');
+            let token = params.next().unwrap_or_default().trim();
+            let q = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if !token.is_empty() {
+                weights.push((token, q));
+            }
+        }
+    }
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn accepted(value: &str) -> Vec<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_str(value).unwrap(),
+        );
+        accepted_encodings(&headers)
+    }
+
+    #[test]
+    fn test_accepted_encodings_uses_preference_for_ties() {
+        assert_eq!(
+            accepted("gzip, deflate, br, zstd"),
+            [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip]
+        );
+        assert_eq!(accepted("gzip"), [Encoding::Gzip]);
+        assert!(accepted("identity").is_empty());
+        assert!(accepted_encodings(&HeaderMap::new()).is_empty());
+    }
+
+    #[test]
+    fn test_accepted_encodings_honors_q_values() {
+        assert_eq!(
+            accepted("br;q=0.5, gzip;q=0.8, zstd;q=0"),
+            [Encoding::Gzip, Encoding::Brotli]
+        );
+        assert_eq!(
+            accepted("*;q=0.1, gzip"),
+            [Encoding::Gzip, Encoding::Brotli, Encoding::Zstd]
+        );
+        assert_eq!(accepted("*, br;q=0"), [Encoding::Zstd, Encoding::Gzip]);
+    }
+
+    #[test]
+    fn test_parse_encoding() {
+        assert_eq!("br".parse::<Encoding>().unwrap(), Encoding::Brotli);
+        assert_eq!(" GZIP ".parse::<Encoding>().unwrap(), Encoding::Gzip);
+        assert!("deflate".parse::<Encoding>().is_err());
+    }
+}
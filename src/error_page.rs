@@ -0,0 +1,253 @@
+//! Error responses that explain what went wrong, as HTML for browsers or JSON for API clients.
+
+use axum::{
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::access_log::UpstreamRequest;
+use crate::encoding::quality_values;
+
+/// An error answered with a page stating what failed and how to fix it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorPage {
+    #[serde(serialize_with = "serialize_status")]
+    pub status: StatusCode,
+    /// ID of the failed request, as shown in the log
+    pub request_id: String,
+    /// What went wrong, in one sentence
+    pub message: String,
+    /// Short error class (e.g. "connection refused"), for proxy failures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// URL the request was forwarded to, for proxy failures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    /// A suggestion for fixing the failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    #[serde(skip)]
+    json: bool,
+}
+
+impl ErrorPage {
+    pub fn new(status: StatusCode, request_id: &str, message: impl Into<String>) -> Self {
+        ErrorPage {
+            status,
+            request_id: request_id.to_string(),
+            message: message.into(),
+            error: None,
+            upstream: None,
+            hint: None,
+            json: false,
+        }
+    }
+
+    pub fn error(mut self, error: impl ToString) -> Self {
+        self.error = Some(error.to_string());
+        self
+    }
+
+    pub fn upstream(mut self, upstream: impl Into<String>) -> Self {
+        self.upstream = Some(upstream.into());
+        self
+    }
+
+    pub fn hint(mut self, hint: Option<String>) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    /// Renders as JSON instead of HTML when the request prefers it
+    pub fn negotiate(mut self, headers: &HeaderMap) -> Self {
+        self.json = prefers_json(headers);
+        self
+    }
+
+    fn html(&self) -> String {
+        let title = format!(
+            "{} {}",
+            self.status.as_u16(),
+            self.status.canonical_reason().unwrap_or("Error")
+        );
+        let mut details = String::new();
+        let rows = [
+            ("Upstream", self.upstream.as_deref()),
+            ("Error", self.error.as_deref()),
+            ("Request ID", Some(self.request_id.as_str())),
+        ];
+        for (name, value) in rows {
+            if let Some(value) = value {
+                details.push_str(&format!(
+                    "<dt>{}</dt><dd><code>{}</code></dd>",
+                    name,
+                    escape_html(value)
+                ));
+            }
+        }
+        let hint = self
+            .hint
+            .as_deref()
+            .map(|hint| format!("<p class=\"hint\">{}</p>", escape_html(hint)))
+            .unwrap_or_default();
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>{message}</p>\n\
+             <dl>{details}</dl>\n{hint}\n<footer>local-rs</footer>\n</body>\n</html>\n",
+            title = title,
+            message = escape_html(&self.message),
+            details = details,
+            hint = hint,
+        )
+    }
+}
+
+impl IntoResponse for ErrorPage {
+    fn into_response(self) -> Response {
+        let (content_type, body) = if self.json {
+            (
+                "application/json",
+                serde_json::to_string(&self).unwrap_or_default(),
+            )
+        } else {
+            ("text/html; charset=utf-8", self.html())
+        };
+        let mut response = (self.status, body).into_response();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        if let Some(url) = self.upstream {
+            response
+                .extensions_mut()
+                .insert(UpstreamRequest { url, latency: None });
+        }
+        response
+    }
+}
+
+const STYLE: &str = "body{font:16px/1.5 system-ui,sans-serif;max-width:40rem;margin:4rem auto;\
+padding:0 1rem;color:#222}h1{font-size:1.5rem}dt{font-weight:600}dd{margin:0 0 .5rem}\
+.hint{padding:.75rem 1rem;background:#fff8e1;border-left:4px solid #f5a623}\
+footer{margin-top:2rem;color:#888;font-size:.85rem}";
+
+/// Whether `Accept` weighs JSON above HTML, or lists it first at equal weight
+///
+/// Browsers ask for `text/html` first; `fetch` calls that expect JSON ask
+/// for it. Wildcards count for neither, so `*/*` gets HTML.
+fn prefers_json(headers: &HeaderMap) -> bool {
+    let weights = quality_values(headers, header::ACCEPT);
+    let find = |media_type: &str| {
+        weights
+            .iter()
+            .position(|(token, _)| token.eq_ignore_ascii_case(media_type))
+            .map(|position| (weights[position].1, position))
+    };
+    match (find("application/json"), find("text/html")) {
+        (Some((json, _)), _) if json <= 0.0 => false,
+        (Some(_), None) => true,
+        (Some((json, json_at)), Some((html, html_at))) => {
+            json > html || (json == html && json_at < html_at)
+        }
+        (None, _) => false,
+    }
+}
+
+fn serialize_status<S: serde::Serializer>(
+    status: &StatusCode,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u16(status.as_u16())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> ErrorPage {
+        ErrorPage::new(
+            StatusCode::BAD_GATEWAY,
+            "abc123",
+            "The API backend could not be reached",
+        )
+        .error("connection refused")
+        .upstream("http://127.0.0.1:8081/pz/users?a=1&b=2")
+        .hint(Some(
+            "Is your backend running on 127.0.0.1:8081?".to_string(),
+        ))
+    }
+
+    fn accepting(accept: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+        headers
+    }
+
+    #[test]
+    fn test_prefers_json() {
+        assert!(prefers_json(&accepting("application/json")));
+        assert!(prefers_json(&accepting("application/json, text/html")));
+        assert!(!prefers_json(&accepting(
+            "text/html,application/xhtml+xml,application/json;q=0.9"
+        )));
+        assert!(!prefers_json(&accepting("*/*")));
+        assert!(!prefers_json(&accepting(
+            "text/plain, application/json;q=0"
+        )));
+        assert!(prefers_json(&accepting(
+            "text/html;q=0.5, application/json;q=0.9"
+        )));
+        assert!(!prefers_json(&accepting(
+            "application/json;q=0.5, text/html"
+        )));
+        assert!(!prefers_json(&HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn test_html_page() {
+        let response = page().into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        assert!(html.contains("<title>502 Bad Gateway</title>"));
+        assert!(html.contains("http://127.0.0.1:8081/pz/users?a=1&amp;b=2"));
+        assert!(html.contains("connection refused"));
+        assert!(html.contains("abc123"));
+        assert!(html.contains("Is your backend running on 127.0.0.1:8081?"));
+    }
+
+    #[tokio::test]
+    async fn test_json_body() {
+        let response = page()
+            .negotiate(&accepting("application/json"))
+            .into_response();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["status"], 502);
+        assert_eq!(json["error"], "connection refused");
+        assert_eq!(json["request_id"], "abc123");
+        assert_eq!(json["upstream"], "http://127.0.0.1:8081/pz/users?a=1&b=2");
+
+        let minimal = ErrorPage::new(StatusCode::NOT_FOUND, "id", "Not found")
+            .negotiate(&accepting("application/json"));
+        let json = serde_json::to_value(&minimal).unwrap();
+        assert!(json.get("upstream").is_none());
+    }
+}
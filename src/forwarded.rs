@@ -0,0 +1,287 @@
+//! `X-Forwarded-*` and RFC 7239 `Forwarded` headers for proxied requests.
+
+use axum::{
+    extract::connect_info::Connected,
+    http::{HeaderMap, HeaderName, HeaderValue, header},
+    serve::IncomingStream,
+};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+use tokio::net::TcpListener;
+
+use crate::tls::TlsListener;
+
+pub const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+pub const X_FORWARDED_PROTO: HeaderName = HeaderName::from_static("x-forwarded-proto");
+pub const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+pub const X_FORWARDED_PREFIX: HeaderName = HeaderName::from_static("x-forwarded-prefix");
+
+/// What to do with forwarding headers that arrive from the client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrustMode {
+    /// Discard them: local-rs is the first proxy and clients can't spoof them
+    #[default]
+    Replace,
+    /// Extend them: local-rs sits behind another proxy that set them
+    Append,
+}
+
+impl fmt::Display for TrustMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TrustMode::Replace => "replace",
+            TrustMode::Append => "append",
+        })
+    }
+}
+
+impl FromStr for TrustMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.trim() {
+            "replace" => Ok(TrustMode::Replace),
+            "append" => Ok(TrustMode::Append),
+            _ => Err(format!(
+                "unknown trust mode '{}' (expected replace or append)",
+                mode
+            )),
+        }
+    }
+}
+
+/// How forwarding headers are added to proxied requests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedConfig {
+    /// Whether incoming forwarding headers are replaced or appended to
+    pub trust: TrustMode,
+    /// Whether to also send a standard `Forwarded` header (RFC 7239)
+    pub rfc7239: bool,
+}
+
+/// The peer address of a client connection
+///
+/// Recorded for each connection with `into_make_service_with_connect_info`
+/// and read back through `ConnectInfo<ClientAddr>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddr(pub SocketAddr);
+
+impl Connected<IncomingStream<'_, TcpListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        ClientAddr(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        ClientAddr(*stream.remote_addr())
+    }
+}
+
+/// What local-rs knows about the request it is forwarding
+#[derive(Debug, Clone, Copy)]
+pub struct ForwardedRequest<'a> {
+    /// Client address, when the server records connection info
+    pub client: Option<IpAddr>,
+    /// Scheme the client used: "http" or "https"
+    pub proto: &'a str,
+    /// Host the client asked for (`Host` header or HTTP/2 authority)
+    pub host: Option<&'a str>,
+    /// Route prefix removed from the upstream path, if any
+    pub prefix: Option<&'a str>,
+}
+
+impl ForwardedConfig {
+    /// Sets the forwarding headers on a request about to be sent upstream
+    ///
+    /// `incoming` holds the client's original headers. In append mode the
+    /// client chain in `X-Forwarded-For` and `Forwarded` is extended, and the
+    /// single-valued headers keep what the proxy in front of us set.
+    pub fn apply(&self, headers: &mut HeaderMap, incoming: &HeaderMap, request: &ForwardedRequest) {
+        for name in [
+            &X_FORWARDED_FOR,
+            &X_FORWARDED_PROTO,
+            &X_FORWARDED_HOST,
+            &X_FORWARDED_PREFIX,
+            &header::FORWARDED,
+        ] {
+            headers.remove(name);
+        }
+        let previous = |name: &HeaderName| match self.trust {
+            TrustMode::Append => joined(incoming, name),
+            TrustMode::Replace => None,
+        };
+
+        let client = request.client.map(|ip| ip.to_string());
+        let chain = [previous(&X_FORWARDED_FOR), client];
+        set(headers, X_FORWARDED_FOR, chain.into_iter().flatten());
+
+        let single = |name: &HeaderName, ours: Option<&str>| {
+            previous(name).or_else(|| ours.map(str::to_string))
+        };
+        set(
+            headers,
+            X_FORWARDED_PROTO,
+            single(&X_FORWARDED_PROTO, Some(request.proto)),
+        );
+        set(
+            headers,
+            X_FORWARDED_HOST,
+            single(&X_FORWARDED_HOST, request.host),
+        );
+        set(
+            headers,
+            X_FORWARDED_PREFIX,
+            single(&X_FORWARDED_PREFIX, request.prefix),
+        );
+
+        if self.rfc7239 {
+            let element = forwarded_element(request);
+            set(
+                headers,
+                header::FORWARDED,
+                [previous(&header::FORWARDED), Some(element)]
+                    .into_iter()
+                    .flatten(),
+            );
+        }
+    }
+}
+
+/// All values of a header joined into one comma-separated list
+fn joined(headers: &HeaderMap, name: &HeaderName) -> Option<String> {
+    let values: Vec<_> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    (!values.is_empty()).then(|| values.join(", "))
+}
+
+/// Sets `name` to the comma-separated `values`, if there are any valid ones
+fn set(headers: &mut HeaderMap, name: HeaderName, values: impl IntoIterator<Item = String>) {
+    let value = values.into_iter().collect::<Vec<_>>().join(", ");
+    if let Ok(value) = HeaderValue::from_str(&value)
+        && !value.is_empty()
+    {
+        headers.insert(name, value);
+    }
+}
+
+/// One `Forwarded` element, e.g. `for=127.0.0.1;proto=http;host=localhost:8000`
+fn forwarded_element(request: &ForwardedRequest) -> String {
+    let mut pairs = Vec::new();
+    pairs.push(format!(
+        "for={}",
+        match request.client {
+            Some(IpAddr::V4(ip)) => ip.to_string(),
+            Some(IpAddr::V6(ip)) => format!("\"[{}]\"", ip),
+            None => "unknown".to_string(),
+        }
+    ));
+    pairs.push(format!("proto={}", request.proto));
+    if let Some(host) = request.host {
+        pairs.push(format!("host={}", quote_if_needed(host)));
+    }
+    pairs.join(";")
+}
+
+/// Quotes values that are not RFC 7230 tokens (e.g. `host:port`)
+fn quote_if_needed(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+    if is_token {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ForwardedRequest<'static> {
+        ForwardedRequest {
+            client: Some("10.0.0.7".parse().unwrap()),
+            proto: "https",
+            host: Some("app.test:8443"),
+            prefix: Some("/auth"),
+        }
+    }
+
+    fn incoming() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.append(X_FORWARDED_FOR, HeaderValue::from_static("203.0.113.1"));
+        headers.append(X_FORWARDED_FOR, HeaderValue::from_static("198.51.100.2"));
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static("http"));
+        headers.insert(
+            header::FORWARDED,
+            HeaderValue::from_static("for=203.0.113.1"),
+        );
+        headers
+    }
+
+    fn applied(config: ForwardedConfig, request: &ForwardedRequest) -> HeaderMap {
+        let incoming = incoming();
+        let mut headers = incoming.clone();
+        config.apply(&mut headers, &incoming, request);
+        headers
+    }
+
+    #[test]
+    fn test_replace_mode_discards_incoming_values() {
+        let headers = applied(ForwardedConfig::default(), &request());
+        assert_eq!(headers[&X_FORWARDED_FOR], "10.0.0.7");
+        assert_eq!(headers[&X_FORWARDED_PROTO], "https");
+        assert_eq!(headers[&X_FORWARDED_HOST], "app.test:8443");
+        assert_eq!(headers[&X_FORWARDED_PREFIX], "/auth");
+        assert!(!headers.contains_key(header::FORWARDED));
+    }
+
+    #[test]
+    fn test_append_mode_extends_chain() {
+        let config = ForwardedConfig {
+            trust: TrustMode::Append,
+            rfc7239: true,
+        };
+        let headers = applied(config, &request());
+        assert_eq!(
+            headers[&X_FORWARDED_FOR],
+            "203.0.113.1, 198.51.100.2, 10.0.0.7"
+        );
+        assert_eq!(headers[&X_FORWARDED_PROTO], "http");
+        assert_eq!(headers[&X_FORWARDED_HOST], "app.test:8443");
+        assert_eq!(
+            headers[header::FORWARDED],
+            "for=203.0.113.1, for=10.0.0.7;proto=https;host=\"app.test:8443\""
+        );
+    }
+
+    #[test]
+    fn test_forwarded_element_quotes_ipv6() {
+        let request = ForwardedRequest {
+            client: Some("::1".parse().unwrap()),
+            proto: "http",
+            host: None,
+            prefix: None,
+        };
+        assert_eq!(forwarded_element(&request), "for=\"[::1]\";proto=http");
+
+        let headers = applied(ForwardedConfig::default(), &request);
+        assert!(!headers.contains_key(&X_FORWARDED_PREFIX));
+        assert!(!headers.contains_key(&X_FORWARDED_HOST));
+    }
+
+    #[test]
+    fn test_parse_trust_mode() {
+        assert_eq!("append".parse::<TrustMode>().unwrap(), TrustMode::Append);
+        assert_eq!("replace".parse::<TrustMode>().unwrap(), TrustMode::Replace);
+        assert!("trust-all".parse::<TrustMode>().is_err());
+    }
+}
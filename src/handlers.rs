@@ -1,34 +1,44 @@
 //! HTTP request handlers.
 
 use axum::{
-    body::{Body, Bytes},
-    extract::{Extension, Path, State},
+    body::Body,
+    extract::{ConnectInfo, Extension, Path, State},
     http::{HeaderMap, HeaderValue, Method, StatusCode, Uri, header},
     response::Response,
 };
 use owo_colors::OwoColorize;
 use std::{
+    fs::Metadata,
+    io,
     path::{Path as FsPath, PathBuf},
     sync::Arc,
     time::Instant,
 };
-use tokio::fs;
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
 use tracing::info;
 
+use crate::access_log::UpstreamRequest;
 use crate::colors::colored_id;
+use crate::encoding::{Encoding, accepted_encodings};
+use crate::error_page::ErrorPage;
+use crate::forwarded::{ClientAddr, ForwardedRequest};
+use crate::live_reload::inject_client;
+use crate::ranges::{ByteRange, RangeRequest, closing_delimiter, parse_range, part_header};
+use crate::request_body::{declared_too_large, upstream_body};
+use crate::rewrite::{RewriteRule, rewrite_path};
+use crate::shutdown::InFlight;
 use crate::state::AppState;
+use crate::trace_context::RequestTrace;
+use crate::tunnel::{ClientUpgrade, spawn_tunnel};
+use crate::upstream;
+use crate::validators::Validators;
 
-/// Headers that should not be forwarded in proxy requests
-const HOP_BY_HOP_REQUEST_HEADERS: &[&str] =
-    &["host", "accept-encoding", "connection", "keep-alive"];
-
-/// Headers that should not be forwarded in proxy responses
-const HOP_BY_HOP_RESPONSE_HEADERS: &[&str] = &[
-    "transfer-encoding",
-    "content-encoding",
-    "connection",
-    "keep-alive",
-];
+/// Size of the chunks static files are streamed in
+const STATIC_CHUNK_SIZE: usize = 64 * 1024;
 
 /// Resolves a URI path to a file system path, handling index.html fallback
 ///
@@ -54,32 +64,163 @@ pub fn resolve_static_path(static_dir: &FsPath, uri_path: &str) -> PathBuf {
 
     file_path
 }
-/// Filters out hop-by-hop headers from request headers
+
+/// Opens a static file for streaming, rejecting anything that is not a regular file
+pub async fn open_static_file(path: &FsPath) -> io::Result<(fs::File, Metadata)> {
+    let file = fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(io::Error::from(io::ErrorKind::NotFound));
+    }
+    Ok((file, metadata))
+}
+
+/// Opens the best precompressed sibling of `path` the client accepts (`app.js.br`)
 ///
-/// These headers are connection-specific and should not be forwarded
-/// through a proxy to the backend server.
-pub fn filter_request_headers(headers: &HeaderMap) -> HeaderMap {
-    let mut filtered = HeaderMap::new();
-    for (key, value) in headers.iter() {
-        if !HOP_BY_HOP_REQUEST_HEADERS.contains(&key.as_str()) {
-            filtered.insert(key.clone(), value.clone());
+/// Returns the sibling's path alongside the opened file, or `None` when the
+/// client accepts no coding with a sibling on disk.
+pub async fn open_precompressed(
+    path: &FsPath,
+    headers: &HeaderMap,
+) -> Option<(Encoding, PathBuf, fs::File, Metadata)> {
+    for encoding in accepted_encodings(headers) {
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(".");
+        sibling.push(encoding.extension());
+        let sibling = PathBuf::from(sibling);
+
+        if let Ok((file, metadata)) = open_static_file(&sibling).await {
+            return Some((encoding, sibling, file, metadata));
         }
     }
-    filtered
+    None
 }
 
-/// Filters out hop-by-hop headers from response headers
+/// Builds the response for an opened static file, honoring `Range` requests
 ///
-/// These headers are connection-specific and should not be forwarded
-/// from the backend server to the client.
-pub fn filter_response_headers(headers: &HeaderMap) -> HeaderMap {
-    let mut filtered = HeaderMap::new();
-    for (key, value) in headers.iter() {
-        if !HOP_BY_HOP_RESPONSE_HEADERS.contains(&key.as_str()) {
-            filtered.insert(key.clone(), value.clone());
+/// Single ranges are answered with the requested slice, several ranges with a
+/// `multipart/byteranges` body; `If-Range` falls back to the full file when the
+/// file changed since the client's copy.
+pub async fn file_response(
+    mut file: fs::File,
+    metadata: &Metadata,
+    validators: &Validators,
+    file_path: &FsPath,
+    content_type: &str,
+    headers: &HeaderMap,
+) -> io::Result<Response> {
+    let size = metadata.len();
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| {
+            headers
+                .get(header::IF_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .is_none_or(|if_range| validators.if_range_matches(if_range))
+        })
+        .map_or(RangeRequest::Full, |range| parse_range(range, size));
+
+    let mut response = match range {
+        RangeRequest::Full => {
+            let mut response = Response::new(stream_body(file));
+            response
+                .headers_mut()
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(size));
+            response
+        }
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            file.seek(io::SeekFrom::Start(range.start)).await?;
+
+            let mut response = Response::new(stream_body(file.take(range.byte_count())));
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let headers = response.headers_mut();
+            headers.insert(
+                header::CONTENT_LENGTH,
+                HeaderValue::from(range.byte_count()),
+            );
+            headers.insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&range.content_range(size)).unwrap(),
+            );
+            response
+        }
+        RangeRequest::Partial(ranges) => {
+            multipart_response(file_path, content_type, &ranges, size).await?
         }
+        RangeRequest::Unsatisfiable => {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            response.headers_mut().insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes */{}", size)).unwrap(),
+            );
+            response
+        }
+    };
+
+    response
+        .headers_mut()
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    Ok(response)
+}
+
+/// Streams a reader as a response body in [`STATIC_CHUNK_SIZE`] chunks
+fn stream_body(reader: impl AsyncRead + Send + 'static) -> Body {
+    Body::from_stream(ReaderStream::with_capacity(reader, STATIC_CHUNK_SIZE))
+}
+
+/// Builds a `multipart/byteranges` response, streaming each range from its own file handle
+async fn multipart_response(
+    file_path: &FsPath,
+    content_type: &str,
+    ranges: &[ByteRange],
+    size: u64,
+) -> io::Result<Response> {
+    let boundary = nanoid::nanoid!(24);
+    let mut body: std::pin::Pin<Box<dyn AsyncRead + Send>> = Box::pin(tokio::io::empty());
+    let mut length = 0;
+
+    for range in ranges {
+        let header = part_header(&boundary, content_type, range, size);
+        let mut part = fs::File::open(file_path).await?;
+        part.seek(io::SeekFrom::Start(range.start)).await?;
+
+        length += header.len() as u64 + range.byte_count();
+        body = Box::pin(
+            body.chain(io::Cursor::new(header))
+                .chain(part.take(range.byte_count())),
+        );
     }
-    filtered
+    let closing = closing_delimiter(&boundary);
+    length += closing.len() as u64;
+    body = Box::pin(body.chain(io::Cursor::new(closing)));
+
+    let mut response = Response::new(stream_body(body));
+    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", boundary)).unwrap(),
+    );
+    Ok(response)
+}
+
+/// Checks whether a request that missed the static files is a client-side route
+///
+/// Only extension-less paths requested by a browser navigation (an `Accept`
+/// header listing `text/html`) qualify, so missing assets like `/app.js`
+/// still produce a real 404.
+pub fn is_spa_navigation(uri_path: &str, headers: &HeaderMap) -> bool {
+    let has_extension = FsPath::new(uri_path).extension().is_some();
+    let accepts_html = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+
+    !has_extension && accepts_html
 }
 
 /// Builds the full API URL from components
@@ -89,6 +230,7 @@ pub fn filter_response_headers(headers: &HeaderMap) -> HeaderMap {
 /// * `api_path` - The API path prefix (e.g., "/api")
 /// * `request_path` - The path from the request (e.g., "users/123")
 /// * `query` - Optional query string
+/// * `rewrites` - Rules applied to the joined upstream path, first match wins
 ///
 /// # Returns
 /// The complete URL with query string if present
@@ -97,13 +239,10 @@ pub fn build_api_url(
     api_path: &str,
     request_path: &str,
     query: Option<&str>,
+    rewrites: &[RewriteRule],
 ) -> String {
-    let base_url = format!(
-        "{}{}/{}",
-        api_base_url,
-        api_path,
-        request_path.trim_start_matches('/')
-    );
+    let upstream_path = format!("{}/{}", api_path, request_path.trim_start_matches('/'));
+    let base_url = format!("{}{}", api_base_url, rewrite_path(rewrites, &upstream_path));
 
     match query {
         Some(q) => format!("{}?{}", base_url, q),
@@ -112,29 +251,111 @@ pub fn build_api_url(
 }
 
 /// Handles static file requests with proper content-type detection and logging
+///
+/// Missing files are answered with a 404 [`ErrorPage`].
 pub async fn serve_static(
     State(state): State<Arc<AppState>>,
     Extension(id): Extension<String>,
     Extension(start_time): Extension<Instant>,
+    method: Method,
+    headers: HeaderMap,
     uri: Uri,
-) -> Result<Response, StatusCode> {
-    let file_path = resolve_static_path(&state.static_dir, uri.path());
+) -> Result<Response, ErrorPage> {
+    let mut file_path = resolve_static_path(&state.static_dir, uri.path());
+    let mut result = open_static_file(&file_path).await;
+
+    if result.is_err()
+        && let Some(fallback) = &state.spa_fallback
+        && method == Method::GET
+        && is_spa_navigation(uri.path(), &headers)
+    {
+        file_path = fallback.clone();
+        result = open_static_file(&file_path).await;
+    }
 
-    match fs::read(&file_path).await {
-        Ok(content) => {
-            let mime_type = mime_guess::from_path(&file_path).first_or_octet_stream();
-            let mut response = Response::new(Body::from(content));
-            response.headers_mut().insert(
-                header::CONTENT_TYPE,
-                HeaderValue::from_str(mime_type.as_ref()).unwrap(),
-            );
+    // Live reload has to rewrite HTML pages, which are read whole; every other
+    // file is streamed so large assets never sit in memory
+    let mime_type = mime_guess::from_path(&file_path).first_or_octet_stream();
+    let inject = state.live_reload.is_some() && mime_type == mime_guess::mime::TEXT_HTML;
+
+    // Precompressed siblings replace the raw file, which must exist as well
+    let mut encoding = None;
+    if result.is_ok()
+        && !inject
+        && let Some((sibling_encoding, sibling_path, file, metadata)) =
+            open_precompressed(&file_path, &headers).await
+    {
+        encoding = Some(sibling_encoding);
+        file_path = sibling_path;
+        result = Ok((file, metadata));
+    }
+
+    let result = match result {
+        Ok((mut file, metadata)) => {
+            let mut validators = Validators::from_metadata(&metadata);
+            // The injected page is not the file on disk, so it gets its own tag
+            if inject {
+                validators = validators.derived("lr");
+            }
+            let response = if validators.is_not_modified(&headers) {
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::NOT_MODIFIED;
+                Ok(response)
+            } else if inject {
+                let mut content = Vec::new();
+                file.read_to_end(&mut content)
+                    .await
+                    .map(|_| Response::new(Body::from(inject_client(&content))))
+            } else {
+                let content_type = mime_type.as_ref();
+                file_response(
+                    file,
+                    &metadata,
+                    &validators,
+                    &file_path,
+                    content_type,
+                    &headers,
+                )
+                .await
+            };
+
+            response.map(|mut response| {
+                let response_headers = response.headers_mut();
+                validators.apply(response_headers);
+                response_headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+                if let Some(encoding) = encoding {
+                    response_headers.insert(
+                        header::CONTENT_ENCODING,
+                        HeaderValue::from_static(encoding.as_str()),
+                    );
+                }
+                response
+            })
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(mut response) => {
+            let cached = response.status() == StatusCode::NOT_MODIFIED;
+            if !cached && !response.headers().contains_key(header::CONTENT_TYPE) {
+                response.headers_mut().insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_str(mime_type.as_ref()).unwrap(),
+                );
+            }
 
             let latency = start_time.elapsed();
             info!(
-                "{} ← {} {} ({}ms)",
+                "{} ← {} {}{} ({}ms)",
                 colored_id(&id),
                 "STATIC".green(),
                 response.status(),
+                if cached {
+                    format!(" {}", "cache hit".cyan())
+                } else {
+                    String::new()
+                },
                 latency.as_millis()
             );
             Ok(response)
@@ -148,52 +369,178 @@ pub async fn serve_static(
                 StatusCode::NOT_FOUND,
                 latency.as_millis()
             );
-            Err(StatusCode::NOT_FOUND)
+            Err(ErrorPage::new(
+                StatusCode::NOT_FOUND,
+                &id,
+                format!("No file matches {}", uri.path()),
+            )
+            .negotiate(&headers))
         }
     }
 }
 
-/// Proxies API requests to the backend with full headers/body passthrough
+/// Proxies requests to the backend of the matching route with full headers/body passthrough
+///
+/// `Upgrade` requests (e.g. WebSocket handshakes) are forwarded with their
+/// upgrade headers intact; if the backend switches protocols, the two
+/// connections are tunneled until either side closes.
+///
+/// The request body is streamed to the backend as it arrives. Bodies over
+/// the configured max size are answered with 413 Payload Too Large.
+///
+/// Failures are answered with an [`ErrorPage`] naming the upstream URL, the
+/// kind of error and a hint for fixing it.
 #[allow(clippy::too_many_arguments)]
 pub async fn proxy_api(
     State(state): State<Arc<AppState>>,
     Path(path): Path<String>,
     Extension(id): Extension<String>,
     Extension(start_time): Extension<Instant>,
+    client: Option<Extension<ConnectInfo<ClientAddr>>>,
     method: Method,
     headers: HeaderMap,
     uri: Uri,
-    body: Bytes,
-) -> Result<Response, StatusCode> {
-    let full_url = build_api_url(&state.api_base_url, &state.api_path, &path, uri.query());
-    let filtered_headers = filter_request_headers(&headers);
+    upgrade: Option<ClientUpgrade>,
+    trace: Option<Extension<RequestTrace>>,
+    in_flight: Option<Extension<InFlight>>,
+    body: Body,
+) -> Result<Response, ErrorPage> {
+    let error_page =
+        |status, message: String| ErrorPage::new(status, &id, message).negotiate(&headers);
+    let Some(route) = state.route_for(uri.path()) else {
+        tracing::error!("{} No proxy route matches {}", colored_id(&id), uri.path());
+        return Err(error_page(
+            StatusCode::NOT_FOUND,
+            format!("No proxy route matches {}", uri.path()),
+        ));
+    };
+    let label = route.label.as_str();
+    let too_large = |limit: u64| {
+        tracing::error!(
+            "{} Request body exceeds the {} byte limit",
+            colored_id(&id),
+            limit
+        );
+        error_page(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("The request body exceeds the {} byte limit", limit),
+        )
+        .hint(Some(
+            "Raise --max-body-size to allow larger uploads".to_string(),
+        ))
+    };
+    if let Some(limit) = state.max_body_size
+        && declared_too_large(&headers, limit)
+    {
+        return Err(too_large(limit));
+    }
+    let full_url = build_api_url(
+        &route.upstream,
+        route.forwarded_prefix(),
+        &path,
+        uri.query(),
+        &route.rewrites,
+    );
+    let mut filtered_headers = state.hop_by_hop.request(&headers);
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| uri.authority().map(|authority| authority.as_str()));
+    state.forwarded.apply(
+        &mut filtered_headers,
+        &headers,
+        &ForwardedRequest {
+            client: client.map(|Extension(ConnectInfo(ClientAddr(addr)))| addr.ip()),
+            proto: if state.https { "https" } else { "http" },
+            host,
+            // Only a stripped prefix is invisible to the backend
+            prefix: route.strip_prefix.then_some(route.prefix.as_str()),
+        },
+    );
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        filtered_headers.insert(state.request_id_header.0.clone(), value);
+    }
+    if let Some(upstream_host) = route.upstream_host(host) {
+        filtered_headers.insert(header::HOST, upstream_host);
+    }
+    for (name, value) in &route.headers {
+        filtered_headers.insert(name.clone(), value.clone());
+    }
+    if let Some(upgrade) = &upgrade {
+        upgrade.restore_headers(&mut filtered_headers);
+    }
+    let span =
+        trace.map(|Extension(trace)| trace.client_span(&method, &full_url, &mut filtered_headers));
 
-    info!("{} → {} {}", colored_id(&id), "API".yellow(), full_url);
+    info!("{} → {} {}", colored_id(&id), label.yellow(), full_url);
     let proxy_start_time = Instant::now();
 
-    let response = state
-        .client
+    let (body, overflow) = upstream_body(body, state.max_body_size);
+    let mut request = state
+        .clients
+        .get(route.timeouts.connect)
         .request(method.clone(), &full_url)
-        .headers(filtered_headers)
-        .body(body)
-        .send()
-        .await
-        .map_err(|e| {
-            tracing::error!("API request failed: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
+        .headers(filtered_headers);
+    if let Some(body) = body {
+        request = request.body(body);
+    }
+    // A tunnel lives as long as either side wants it to
+    if let Some(total) = route.timeouts.total
+        && upgrade.is_none()
+    {
+        request = request.timeout(total);
+    }
+    let response = upstream::send(request, route.timeouts.first_byte).await;
+    if let Some(span) = span {
+        span.end(match &response {
+            Ok(response) => Ok(response.status()),
+            Err(e) => Err(e.kind.as_str()),
+        });
+    }
+    let response = response.map_err(|e| {
+        if overflow.occurred() {
+            return too_large(state.max_body_size.unwrap_or_default());
+        }
+        if let Some(metrics) = &state.metrics {
+            metrics.upstream_error(label, e.kind);
+        }
+        tracing::error!(
+            "{} {} request failed ({}): {}",
+            colored_id(&id),
+            label,
+            e.kind,
+            e.message
+        );
+        error_page(
+            e.kind.status(),
+            format!("The {} backend request failed: {}", label, e.message),
+        )
+        .error(e.kind)
+        .upstream(&full_url)
+        .hint(e.kind.hint(&route.upstream))
+    })?;
 
     let proxy_latency = proxy_start_time.elapsed();
     info!(
         "{} ← {} {} ({}ms)",
         colored_id(&id),
-        "API".yellow(),
+        label.yellow(),
         response.status(),
         proxy_latency.as_millis()
     );
 
-    let filtered_response_headers = filter_response_headers(response.headers());
-    let mut builder = Response::builder().status(response.status());
+    let mut filtered_response_headers = state.hop_by_hop.response(response.headers());
+    let tunnel = upgrade.filter(|_| response.status() == StatusCode::SWITCHING_PROTOCOLS);
+    if let Some(upgrade) = &tunnel {
+        upgrade.restore_headers(&mut filtered_response_headers);
+    }
+
+    let mut builder = Response::builder()
+        .status(response.status())
+        .extension(UpstreamRequest {
+            url: full_url,
+            latency: Some(proxy_latency),
+        });
     for (key, value) in filtered_response_headers.iter() {
         builder = builder.header(key, value);
     }
@@ -207,15 +554,26 @@ pub async fn proxy_api(
         total_latency.as_millis()
     );
 
-    builder
-        .body(Body::from_stream(response.bytes_stream()))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    let body = match tunnel {
+        Some(upgrade) => {
+            let guard = in_flight.map(|Extension(in_flight)| in_flight.enter());
+            spawn_tunnel(id.clone(), upgrade, response, guard, state.shutdown.clone());
+            Body::empty()
+        }
+        None => Body::from_stream(response.bytes_stream()),
+    };
+
+    builder.body(body).map_err(|e| {
+        error_page(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Invalid response from the {} backend: {}", label, e),
+        )
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use axum::http::HeaderName;
     use std::fs;
 
     #[test]
@@ -273,61 +631,36 @@ mod tests {
     }
 
     #[test]
-    fn test_filter_request_headers_removes_hop_by_hop() {
+    fn test_is_spa_navigation_for_html_route() {
         let mut headers = HeaderMap::new();
         headers.insert(
-            HeaderName::from_static("host"),
-            HeaderValue::from_static("example.com"),
+            header::ACCEPT,
+            HeaderValue::from_static("text/html,application/xhtml+xml,*/*;q=0.8"),
         );
-        headers.insert(
-            HeaderName::from_static("connection"),
-            HeaderValue::from_static("keep-alive"),
-        );
-        headers.insert(
-            HeaderName::from_static("x-custom"),
-            HeaderValue::from_static("value"),
-        );
-        headers.insert(
-            HeaderName::from_static("accept-encoding"),
-            HeaderValue::from_static("gzip"),
-        );
-
-        let filtered = filter_request_headers(&headers);
-
-        assert!(!filtered.contains_key("host"));
-        assert!(!filtered.contains_key("connection"));
-        assert!(!filtered.contains_key("accept-encoding"));
-        assert!(filtered.contains_key("x-custom"));
-        assert_eq!(filtered.get("x-custom").unwrap(), "value");
+        assert!(is_spa_navigation("/users/42", &headers));
+        assert!(is_spa_navigation("/", &headers));
     }
 
     #[test]
-    fn test_filter_response_headers_removes_hop_by_hop() {
+    fn test_is_spa_navigation_rejects_assets() {
         let mut headers = HeaderMap::new();
-        headers.insert(
-            HeaderName::from_static("transfer-encoding"),
-            HeaderValue::from_static("chunked"),
-        );
-        headers.insert(
-            HeaderName::from_static("content-type"),
-            HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            HeaderName::from_static("connection"),
-            HeaderValue::from_static("close"),
-        );
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/html"));
+        assert!(!is_spa_navigation("/app.js", &headers));
+        assert!(!is_spa_navigation("/assets/logo.png", &headers));
+    }
 
-        let filtered = filter_response_headers(&headers);
+    #[test]
+    fn test_is_spa_navigation_requires_html_accept() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_spa_navigation("/users/42", &headers));
 
-        assert!(!filtered.contains_key("transfer-encoding"));
-        assert!(!filtered.contains_key("connection"));
-        assert!(filtered.contains_key("content-type"));
-        assert_eq!(filtered.get("content-type").unwrap(), "application/json");
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        assert!(!is_spa_navigation("/users/42", &headers));
     }
 
     #[test]
     fn test_build_api_url_without_query() {
-        let url = build_api_url("http://localhost:8081", "/api", "users/123", None);
+        let url = build_api_url("http://localhost:8081", "/api", "users/123", None, &[]);
         assert_eq!(url, "http://localhost:8081/api/users/123");
     }
 
@@ -338,13 +671,27 @@ mod tests {
             "/api",
             "users",
             Some("page=1&limit=10"),
+            &[],
         );
         assert_eq!(url, "http://localhost:8081/api/users?page=1&limit=10");
     }
 
     #[test]
     fn test_build_api_url_strips_leading_slash() {
-        let url = build_api_url("http://localhost:8081", "/api", "/users/123", None);
+        let url = build_api_url("http://localhost:8081", "/api", "/users/123", None, &[]);
         assert_eq!(url, "http://localhost:8081/api/users/123");
     }
+
+    #[test]
+    fn test_build_api_url_applies_rewrites() {
+        let rewrites = vec!["strip:/api/v1".parse().unwrap()];
+        let url = build_api_url(
+            "http://localhost:8081",
+            "/api",
+            "v1/users",
+            Some("page=2"),
+            &rewrites,
+        );
+        assert_eq!(url, "http://localhost:8081/users?page=2");
+    }
 }
@@ -0,0 +1,222 @@
+//! Hop-by-hop header filtering for proxied messages (RFC 9110 §7.6.1).
+
+use axum::http::{HeaderMap, HeaderName, header};
+
+/// Connection-specific fields that a proxy must not forward
+///
+/// `Proxy-Connection` is not standard but still sent by some clients.
+pub static HOP_BY_HOP_HEADERS: [HeaderName; 9] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Strips hop-by-hop headers from messages passing through the proxy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HopByHop {
+    /// Additional headers stripped in both directions
+    pub extra: Vec<HeaderName>,
+}
+
+impl HopByHop {
+    pub fn new(extra: Vec<HeaderName>) -> Self {
+        HopByHop { extra }
+    }
+
+    /// Headers to send upstream for a client request
+    ///
+    /// `Host` is dropped as well: the HTTP client sets the upstream's own,
+    /// unless the route asks to preserve or override it.
+    pub fn request(&self, headers: &HeaderMap) -> HeaderMap {
+        let mut filtered = self.filter(headers);
+        filtered.remove(header::HOST);
+        filtered
+    }
+
+    /// Headers to send to the client for an upstream response
+    pub fn response(&self, headers: &HeaderMap) -> HeaderMap {
+        self.filter(headers)
+    }
+
+    /// Copies `headers` without hop-by-hop fields, keeping repeated fields
+    /// such as `Set-Cookie` intact
+    ///
+    /// Besides the fixed set, any field named in `Connection` is
+    /// connection-specific too.
+    fn filter(&self, headers: &HeaderMap) -> HeaderMap {
+        let listed = connection_options(headers);
+        let mut filtered = HeaderMap::with_capacity(headers.len());
+        for (name, value) in headers {
+            if !HOP_BY_HOP_HEADERS.contains(name)
+                && !self.extra.contains(name)
+                && !listed.contains(name)
+            {
+                filtered.append(name.clone(), value.clone());
+            }
+        }
+        filtered
+    }
+}
+
+/// The field names listed in all `Connection` headers
+fn connection_options(headers: &HeaderMap) -> Vec<HeaderName> {
+    headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|option| HeaderName::from_bytes(option.trim().as_bytes()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    /// Case name, input headers, and the names expected to survive
+    type Case = (
+        &'static str,
+        &'static [(&'static str, &'static str)],
+        &'static [&'static str],
+    );
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn names(headers: &HeaderMap) -> Vec<&str> {
+        let mut names: Vec<_> = headers.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_request_filter() {
+        let cases: &[Case] = &[
+            (
+                "fixed hop-by-hop set",
+                &[
+                    ("connection", "keep-alive"),
+                    ("keep-alive", "timeout=5"),
+                    ("proxy-connection", "keep-alive"),
+                    ("proxy-authorization", "Basic Zm9vOmJhcg=="),
+                    ("te", "trailers"),
+                    ("trailer", "expires"),
+                    ("transfer-encoding", "chunked"),
+                    ("upgrade", "websocket"),
+                    ("accept", "*/*"),
+                ],
+                &["accept"],
+            ),
+            (
+                "host is set by the client library",
+                &[("host", "example.com"), ("x-custom", "value")],
+                &["x-custom"],
+            ),
+            (
+                "end-to-end headers pass",
+                &[
+                    ("accept-encoding", "gzip"),
+                    ("authorization", "Bearer token"),
+                    ("cookie", "a=1"),
+                ],
+                &["accept-encoding", "authorization", "cookie"],
+            ),
+            (
+                "fields listed in connection",
+                &[
+                    ("connection", "close, X-Trace-Hop ,x-debug"),
+                    ("x-trace-hop", "1"),
+                    ("x-debug", "1"),
+                    ("x-keep", "1"),
+                ],
+                &["x-keep"],
+            ),
+            (
+                "options across several connection headers",
+                &[
+                    ("connection", "x-a"),
+                    ("connection", "x-b"),
+                    ("x-a", "1"),
+                    ("x-b", "1"),
+                    ("x-c", "1"),
+                ],
+                &["x-c"],
+            ),
+            (
+                "user-configured extras",
+                &[("x-internal-token", "secret"), ("x-keep", "1")],
+                &["x-keep"],
+            ),
+        ];
+
+        let filter = HopByHop::new(vec![HeaderName::from_static("x-internal-token")]);
+        for (case, input, expected) in cases {
+            assert_eq!(
+                names(&filter.request(&headers(input))),
+                *expected,
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn test_response_filter() {
+        let cases: &[Case] = &[
+            (
+                "fixed hop-by-hop set",
+                &[
+                    ("transfer-encoding", "chunked"),
+                    ("connection", "close"),
+                    ("proxy-authenticate", "Basic"),
+                    ("trailer", "expires"),
+                    ("content-type", "application/json"),
+                ],
+                &["content-type"],
+            ),
+            (
+                "content coding and host are end-to-end",
+                &[("content-encoding", "gzip"), ("host", "example.com")],
+                &["content-encoding", "host"],
+            ),
+            (
+                "fields listed in connection",
+                &[("connection", "x-backend-hop"), ("x-backend-hop", "1")],
+                &[],
+            ),
+        ];
+
+        let filter = HopByHop::default();
+        for (case, input, expected) in cases {
+            assert_eq!(
+                names(&filter.response(&headers(input))),
+                *expected,
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn test_filter_keeps_repeated_fields() {
+        let input = headers(&[("set-cookie", "a=1"), ("set-cookie", "b=2")]);
+        let filtered = HopByHop::default().response(&input);
+        let cookies: Vec<_> = filtered.get_all(header::SET_COOKIE).iter().collect();
+        assert_eq!(cookies, ["a=1", "b=2"]);
+    }
+}
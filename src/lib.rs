@@ -1,7 +1,30 @@
 //! Local-rs library - High-performance reverse proxy server.
 
+pub mod access_log;
 pub mod cli;
 pub mod colors;
+pub mod compression;
+pub mod config;
+pub mod encoding;
+pub mod error_page;
+pub mod forwarded;
 pub mod handlers;
+pub mod hop_by_hop;
+pub mod live_reload;
+pub mod log_file;
+pub mod metrics;
 pub mod middleware;
+pub mod otlp;
+pub mod ranges;
+pub mod reload;
+pub mod request_body;
+pub mod rewrite;
+pub mod router;
+pub mod routes;
+pub mod shutdown;
 pub mod state;
+pub mod tls;
+pub mod trace_context;
+pub mod tunnel;
+pub mod upstream;
+pub mod validators;
@@ -0,0 +1,230 @@
+//! Live reload: watches the static directory and pushes reloads to the browser.
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{Stream, StreamExt, stream};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use owo_colors::OwoColorize;
+use std::{
+    convert::Infallible,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info};
+
+use crate::state::AppState;
+
+/// Path of the Server-Sent Events endpoint the injected client listens on
+pub const LIVE_RELOAD_PATH: &str = "/__local/live-reload";
+
+/// Quiet period after the last change before a reload is pushed
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Reload events buffered for slow browsers before older ones are dropped
+const EVENT_CAPACITY: usize = 16;
+
+/// Client script injected into served HTML pages
+///
+/// CSS-only changes re-fetch the stylesheets in place; anything else reloads
+/// the page. The `EventSource` reconnects on its own after server restarts.
+const CLIENT_SCRIPT: &str = r#"<script>
+(() => {
+  const source = new EventSource("/__local/live-reload");
+  source.onmessage = (event) => {
+    if (event.data !== "css") {
+      location.reload();
+      return;
+    }
+    for (const link of document.querySelectorAll('link[rel="stylesheet"]')) {
+      const url = new URL(link.href);
+      url.searchParams.set("live-reload", Date.now());
+      link.href = url.href;
+    }
+  };
+})();
+</script>
+"#;
+
+/// What the browser should do after a batch of file changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadEvent {
+    /// Only stylesheets changed, swap them without reloading the page
+    Css,
+    /// Reload the whole page
+    Full,
+}
+
+impl ReloadEvent {
+    /// Classifies a debounced batch of changed paths
+    pub fn for_paths(paths: &[PathBuf]) -> Self {
+        let css_only = paths.iter().all(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("css"))
+        });
+
+        if css_only && !paths.is_empty() {
+            ReloadEvent::Css
+        } else {
+            ReloadEvent::Full
+        }
+    }
+}
+
+impl fmt::Display for ReloadEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReloadEvent::Css => write!(f, "css"),
+            ReloadEvent::Full => write!(f, "reload"),
+        }
+    }
+}
+
+/// Fan-out of reload events to every connected browser
+#[derive(Debug, Clone)]
+pub struct LiveReload {
+    sender: broadcast::Sender<ReloadEvent>,
+}
+
+impl LiveReload {
+    /// Creates a hub with no connected browsers
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        LiveReload { sender }
+    }
+
+    /// Registers a browser for future reload events
+    pub fn subscribe(&self) -> broadcast::Receiver<ReloadEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Pushes an event to every connected browser
+    pub fn notify(&self, event: ReloadEvent) {
+        let browsers = self.sender.send(event).unwrap_or(0);
+        info!("{} {} ({} browsers)", "RELOAD".magenta(), event, browsers);
+    }
+}
+
+impl Default for LiveReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Watches `dir` recursively and pushes debounced reload events
+///
+/// The returned watcher must be kept alive for as long as changes should be
+/// picked up.
+pub fn watch(dir: &Path, live_reload: LiveReload) -> notify::Result<RecommendedWatcher> {
+    let (changes, pending) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                for path in event.paths {
+                    let _ = changes.send(path);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!("File watcher error: {}", e),
+        })?;
+
+    watcher.watch(dir, RecursiveMode::Recursive)?;
+    tokio::spawn(debounce(pending, live_reload));
+    Ok(watcher)
+}
+
+/// Collects changes until they settle, then pushes a single reload event
+async fn debounce(mut pending: mpsc::UnboundedReceiver<PathBuf>, live_reload: LiveReload) {
+    while let Some(first) = pending.recv().await {
+        let mut paths = vec![first];
+        let quiet = tokio::time::sleep(DEBOUNCE);
+        tokio::pin!(quiet);
+
+        loop {
+            tokio::select! {
+                _ = &mut quiet => break,
+                Some(path) = pending.recv() => {
+                    paths.push(path);
+                    quiet.as_mut().reset(tokio::time::Instant::now() + DEBOUNCE);
+                }
+            }
+        }
+
+        live_reload.notify(ReloadEvent::for_paths(&paths));
+    }
+}
+
+/// Inserts the live reload client before `</body>`, or appends it when there is none
+pub fn inject_client(html: &[u8]) -> Vec<u8> {
+    let position = html
+        .windows(b"</body>".len())
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+
+    let mut injected = Vec::with_capacity(html.len() + CLIENT_SCRIPT.len());
+    injected.extend_from_slice(&html[..position]);
+    injected.extend_from_slice(CLIENT_SCRIPT.as_bytes());
+    injected.extend_from_slice(&html[position..]);
+    injected
+}
+
+/// Streams reload events to a browser as Server-Sent Events
+///
+/// The stream ends when the server shuts down, so open tabs don't hold up
+/// connection draining.
+pub async fn live_reload_events(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.live_reload.as_ref().map(LiveReload::subscribe);
+
+    let events = stream::unfold(receiver, |receiver| async move {
+        let mut receiver = receiver?;
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let event = Event::default().data(event.to_string());
+                    return Some((Ok(event), Some(receiver)));
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .take_until(state.shutdown.clone().cancelled_owned());
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_event_for_paths() {
+        let css = vec![PathBuf::from("app.css"), PathBuf::from("theme/dark.CSS")];
+        assert_eq!(ReloadEvent::for_paths(&css), ReloadEvent::Css);
+
+        let mixed = vec![PathBuf::from("app.css"), PathBuf::from("app.js")];
+        assert_eq!(ReloadEvent::for_paths(&mixed), ReloadEvent::Full);
+        assert_eq!(ReloadEvent::for_paths(&[]), ReloadEvent::Full);
+    }
+
+    #[test]
+    fn test_inject_client_before_body_close() {
+        let html = inject_client(b"<html><BODY>hi</BODY></html>");
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<html><BODY>hi<script>"));
+        assert!(html.ends_with("</script>\n</BODY></html>"));
+    }
+
+    #[test]
+    fn test_inject_client_without_body() {
+        let html = String::from_utf8(inject_client(b"<p>fragment</p>")).unwrap();
+        assert!(html.starts_with("<p>fragment</p><script>"));
+        assert!(html.contains(LIVE_RELOAD_PATH));
+    }
+}
@@ -0,0 +1,410 @@
+//! Append-only log files with size or daily rotation.
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender, TrySendError},
+    },
+    thread,
+};
+use time::{Date, OffsetDateTime};
+use tracing::{info, warn};
+
+/// When a log file is moved aside for a fresh one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// Never; external tools can still rotate and send SIGUSR1
+    #[default]
+    Never,
+    /// On the first write of each day (UTC)
+    Daily,
+    /// Before a write would make the file larger than this many bytes
+    Size(u64),
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rotation::Never => f.write_str("never"),
+            Rotation::Daily => f.write_str("daily"),
+            Rotation::Size(bytes) => write!(f, "{}", bytes),
+        }
+    }
+}
+
+/// Parses `never`, `daily` or a size in bytes with an optional `K`, `M` or `G`
+/// suffix (e.g. `10M`)
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(rotation: &str) -> Result<Self, Self::Err> {
+        let rotation = rotation.trim();
+        match rotation {
+            "never" => return Ok(Rotation::Never),
+            "daily" => return Ok(Rotation::Daily),
+            _ => {}
+        }
+        let (digits, unit) = match rotation.char_indices().last() {
+            Some((i, 'K' | 'k')) => (&rotation[..i], 1 << 10),
+            Some((i, 'M' | 'm')) => (&rotation[..i], 1 << 20),
+            Some((i, 'G' | 'g')) => (&rotation[..i], 1 << 30),
+            _ => (rotation, 1),
+        };
+        match digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|size| size.checked_mul(unit))
+        {
+            Some(size) if size > 0 => Ok(Rotation::Size(size)),
+            _ => Err(format!(
+                "invalid rotation '{}' (expected never, daily or a size like 10M)",
+                rotation
+            )),
+        }
+    }
+}
+
+/// Lines waiting for the writer thread; more are dropped rather than
+/// blocking the request that logs them
+const QUEUE_CAPACITY: usize = 8192;
+
+/// A log file shared by all requests
+///
+/// Lines are queued to a dedicated writer thread, so the blocking writes and
+/// renames never run on the async runtime's worker threads.
+#[derive(Debug, Clone)]
+pub struct LogFile {
+    path: PathBuf,
+    queue: SyncSender<Command>,
+    shared: Arc<Shared>,
+}
+
+/// State the handles share with the writer thread
+#[derive(Debug)]
+struct Shared {
+    /// Lines dropped because the queue was full, reported by the writer
+    dropped: AtomicU64,
+    /// Set when a reopen found the queue full; the writer reopens before the
+    /// next command it takes off the queue
+    reopen: AtomicBool,
+    /// Rotation policy and number of rotated files kept, picked up by the
+    /// writer before each line
+    rotation: Mutex<(Rotation, usize)>,
+}
+
+#[derive(Debug)]
+enum Command {
+    Line(String),
+    Reopen,
+    /// Acknowledged once everything queued before it is written
+    Flush(Sender<()>),
+}
+
+impl LogFile {
+    /// Opens `path` for appending, creating it if needed, and starts its writer
+    pub fn open(path: &Path, rotation: Rotation, keep: usize) -> io::Result<Self> {
+        let writer = Writer::open(path, rotation, keep)?;
+        let (queue, commands) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let shared = Arc::new(Shared {
+            dropped: AtomicU64::new(0),
+            reopen: AtomicBool::new(false),
+            rotation: Mutex::new((rotation, keep)),
+        });
+        let writer_shared = shared.clone();
+        thread::Builder::new()
+            .name("log-file".to_string())
+            .spawn(move || writer.run(commands, writer_shared))?;
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            queue,
+            shared,
+        })
+    }
+
+    /// Queues one line for appending; it is dropped if the writer is too far behind
+    pub fn write_line(&self, line: String) {
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(Command::Line(line)) {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Changes the rotation policy of the open file, from the next line on
+    pub fn reconfigure(&self, rotation: Rotation, keep: usize) {
+        *self.shared.rotation.lock().unwrap() = (rotation, keep);
+    }
+
+    /// Reopens the file at its path, after an external tool moved it away
+    ///
+    /// Never waits, so it is safe to call from async code: when the queue is
+    /// full the reopen is flagged instead and happens before the next queued
+    /// line is written.
+    pub fn reopen(&self) {
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(Command::Reopen) {
+            self.shared.reopen.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Blocks until every line queued so far is written
+    ///
+    /// This blocks the calling thread, so it must not be called from async
+    /// code.
+    pub fn flush(&self) {
+        let (done, written) = mpsc::channel();
+        if self.queue.send(Command::Flush(done)).is_ok() {
+            let _ = written.recv();
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The open file, owned by the writer thread
+#[derive(Debug)]
+struct Writer {
+    path: PathBuf,
+    rotation: Rotation,
+    /// Rotated files kept next to the live one (`access.log.1` is the newest)
+    keep: usize,
+    file: File,
+    size: u64,
+    /// Day the current file was started on
+    day: Date,
+}
+
+impl Writer {
+    fn open(path: &Path, rotation: Rotation, keep: usize) -> io::Result<Self> {
+        let (file, size, day) = open_append(path)?;
+        Ok(Writer {
+            path: path.to_path_buf(),
+            rotation,
+            keep,
+            file,
+            size,
+            day,
+        })
+    }
+
+    /// Handles commands until every [`LogFile`] handle is dropped
+    fn run(mut self, commands: Receiver<Command>, shared: Arc<Shared>) {
+        for command in commands {
+            if shared.reopen.swap(false, Ordering::Relaxed)
+                && let Err(e) = self.reopen_logged()
+            {
+                warn!("Cannot write {:?}: {}", self.path, e);
+            }
+            let result = match command {
+                Command::Line(line) => {
+                    (self.rotation, self.keep) = *shared.rotation.lock().unwrap();
+                    self.write_line(&line)
+                }
+                Command::Reopen => self.reopen_logged(),
+                Command::Flush(done) => {
+                    let _ = done.send(());
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                warn!("Cannot write {:?}: {}", self.path, e);
+            }
+            let lost = shared.dropped.swap(0, Ordering::Relaxed);
+            if lost > 0 {
+                warn!(
+                    "Dropped {} lines for {:?}: writer fell behind",
+                    lost, self.path
+                );
+            }
+        }
+    }
+
+    /// Appends one line, rotating first if it is due
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        let due = match self.rotation {
+            Rotation::Never => false,
+            Rotation::Daily => today() != self.day,
+            Rotation::Size(max) => self.size > 0 && self.size + len > max,
+        };
+        if due {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        (self.file, self.size, self.day) = open_append(&self.path)?;
+        Ok(())
+    }
+
+    /// Reopens on request (SIGUSR1), as opposed to after a rotation
+    fn reopen_logged(&mut self) -> io::Result<()> {
+        self.reopen()?;
+        info!("Reopened {:?}", self.path);
+        Ok(())
+    }
+
+    /// Shifts `log.1`..`log.N-1` up by one, moves the live file to `log.1`
+    /// and starts a new one; the oldest beyond `keep` is dropped
+    fn rotate(&mut self) -> io::Result<()> {
+        let numbered = |n: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", n));
+            PathBuf::from(path)
+        };
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                if numbered(n).exists() {
+                    fs::rename(numbered(n), numbered(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, numbered(1))?;
+        }
+        self.reopen()
+    }
+}
+
+/// Opens a file for appending, with its current size and the day it was
+/// last written to
+fn open_append(path: &Path) -> io::Result<(File, u64, Date)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let metadata = file.metadata()?;
+    let day = metadata
+        .modified()
+        .map(|modified| OffsetDateTime::from(modified).date())
+        .unwrap_or_else(|_| today());
+    Ok((file, metadata.len(), day))
+}
+
+fn today() -> Date {
+    OffsetDateTime::now_utc().date()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("target")
+            .join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rotation() {
+        assert_eq!("daily".parse::<Rotation>().unwrap(), Rotation::Daily);
+        assert_eq!("never".parse::<Rotation>().unwrap(), Rotation::Never);
+        assert_eq!("4096".parse::<Rotation>().unwrap(), Rotation::Size(4096));
+        assert_eq!(
+            "10M".parse::<Rotation>().unwrap(),
+            Rotation::Size(10 * 1024 * 1024)
+        );
+        assert_eq!("1g".parse::<Rotation>().unwrap(), Rotation::Size(1 << 30));
+        assert!("0".parse::<Rotation>().is_err());
+        assert!("weekly".parse::<Rotation>().is_err());
+        assert!("M".parse::<Rotation>().is_err());
+        assert!("99999999999G".parse::<Rotation>().is_err());
+        assert!(format!("{}", u64::MAX).parse::<Rotation>().is_ok());
+    }
+
+    #[test]
+    fn test_size_rotation_keeps_n_files() {
+        let dir = log_dir("test_log_size_rotation");
+        let path = dir.join("access.log");
+        let mut log = Writer::open(&path, Rotation::Size(8), 2).unwrap();
+        for line in ["one", "two", "three", "four", "five"] {
+            log.write_line(line).unwrap();
+        }
+
+        // "one" and "two" share a file; from "three" on every line rotates,
+        // and the file holding "one" and "two" falls off the end
+        assert_eq!(read(path.clone()), "five\n");
+        assert_eq!(read(dir.join("access.log.1")), "four\n");
+        assert_eq!(read(dir.join("access.log.2")), "three\n");
+        assert!(!dir.join("access.log.3").exists());
+    }
+
+    #[test]
+    fn test_daily_rotation_on_new_day() {
+        let dir = log_dir("test_log_daily_rotation");
+        let path = dir.join("access.log");
+        let mut log = Writer::open(&path, Rotation::Daily, 3).unwrap();
+        log.write_line("today").unwrap();
+        log.day = today().previous_day().unwrap();
+        log.write_line("tomorrow").unwrap();
+
+        assert_eq!(read(dir.join("access.log.1")), "today\n");
+        assert_eq!(read(path), "tomorrow\n");
+    }
+
+    #[test]
+    fn test_reopen_after_external_rotation() {
+        let dir = log_dir("test_log_reopen");
+        let path = dir.join("access.log");
+        let log = LogFile::open(&path, Rotation::Never, 0).unwrap();
+        log.write_line("before".to_string());
+        log.flush();
+
+        fs::rename(&path, dir.join("access.log.old")).unwrap();
+        log.reopen();
+        log.write_line("after".to_string());
+        log.flush();
+
+        assert_eq!(read(dir.join("access.log.old")), "before\n");
+        assert_eq!(read(path), "after\n");
+    }
+
+    #[test]
+    fn test_flagged_reopen_applies_to_the_next_line() {
+        let dir = log_dir("test_log_flagged_reopen");
+        let path = dir.join("access.log");
+        let log = LogFile::open(&path, Rotation::Never, 0).unwrap();
+        log.write_line("before".to_string());
+        log.flush();
+
+        // As if `reopen` had found the queue full
+        fs::rename(&path, dir.join("access.log.old")).unwrap();
+        log.shared.reopen.store(true, Ordering::Relaxed);
+        log.write_line("after".to_string());
+        log.flush();
+
+        assert_eq!(read(dir.join("access.log.old")), "before\n");
+        assert_eq!(read(path), "after\n");
+    }
+
+    #[test]
+    fn test_reconfigure_keeps_the_open_file() {
+        let dir = log_dir("test_log_reconfigure");
+        let path = dir.join("access.log");
+        let log = LogFile::open(&path, Rotation::Never, 0).unwrap();
+        log.write_line("one".to_string());
+        log.write_line("two".to_string());
+        log.flush();
+        assert_eq!(read(path.clone()), "one\ntwo\n");
+
+        log.reconfigure(Rotation::Size(8), 1);
+        log.write_line("three".to_string());
+        log.flush();
+
+        assert_eq!(read(dir.join("access.log.1")), "one\ntwo\n");
+        assert_eq!(read(path), "three\n");
+        assert!(!dir.join("access.log.2").exists());
+    }
+}
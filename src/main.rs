@@ -1,66 +1,181 @@
 //! A high-performance reverse proxy server with colored request tracing.
 //!
 //! Features:
-//! - Routes API requests to a backend server
+//! - Routes API requests to one or more backend servers by path prefix
 //! - Serves static files from a directory
 //! - Detailed logging with color-coded request IDs
 //! - Latency tracking for both static and API requests
+//! - WebSocket / HTTP Upgrade tunneling to the backend
+//! - Optional on-the-fly compression of static and proxied responses
+//! - Live reload of the browser when static files change
+//! - Optional TLS termination, with locally generated certificates
+//! - TOML config file, with command-line flags taking precedence
+//! - Config hot reload on SIGHUP or file change, without dropping connections
+//! - Graceful shutdown that drains in-flight requests
 
+pub mod access_log;
 pub mod cli;
 pub mod colors;
+pub mod compression;
+pub mod config;
+pub mod encoding;
+pub mod error_page;
+pub mod forwarded;
 pub mod handlers;
+pub mod hop_by_hop;
+pub mod live_reload;
+pub mod log_file;
+pub mod metrics;
 pub mod middleware;
+pub mod otlp;
+pub mod ranges;
+pub mod reload;
+pub mod request_body;
+pub mod rewrite;
+pub mod router;
+pub mod routes;
+pub mod shutdown;
 pub mod state;
+pub mod tls;
+pub mod trace_context;
+pub mod tunnel;
+pub mod upstream;
+pub mod validators;
 
-use axum::{
-    Router, middleware as axum_middleware,
-    routing::{any, get},
-};
-use std::sync::Arc;
-use tracing::{Level, info};
+use axum::middleware as axum_middleware;
+use rustls::ServerConfig;
+use std::{process, sync::Arc};
+use tracing::{Level, error, info};
 
 use crate::cli::Cli;
-use crate::handlers::{proxy_api, serve_static};
-use crate::middleware::log_requests;
-use crate::state::AppState;
+use crate::config::{Config, TlsMode};
+use crate::forwarded::ClientAddr;
+use crate::metrics::METRICS_PATH;
+use crate::reload::Reloader;
+use crate::shutdown::InFlight;
+use crate::tls::TlsListener;
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
-
     let args: Cli = argh::from_env();
-    let canonical_static_dir = args
-        .static_dir
-        .canonicalize()
-        .expect("Failed to canonicalize static directory");
+    let config = Config::load(&args);
 
-    let api_base_url = if args.api.starts_with("http") {
-        args.api
+    // Access log formats own stdout, so everything else moves to stderr
+    let log_format = config.as_ref().map(|c| c.log_format).unwrap_or_default();
+    let logger = tracing_subscriber::fmt().with_max_level(Level::INFO);
+    if log_format.is_access_log() {
+        logger.with_writer(std::io::stderr).init();
     } else {
-        format!("http://{}", args.api)
-    };
+        logger.init();
+    }
 
-    let state = Arc::new(AppState {
-        api_base_url,
-        api_path: args.api_path.trim_end_matches('/').to_string(),
-        static_dir: canonical_static_dir.clone(),
-        client: reqwest::Client::new(),
+    if let Some(path) = config::config_path(&args) {
+        info!("Loading config: {:?}", path);
+    }
+    let config = config.unwrap_or_else(|e| {
+        error!("Invalid configuration: {}", e);
+        process::exit(1);
     });
-
-    let app = Router::new()
-        .route(&format!("{}/{{*path}}", args.api_path), any(proxy_api))
-        .fallback(get(serve_static))
-        .layer(axum_middleware::from_fn(log_requests))
-        .with_state(state.clone());
+    let tls_config = tls_config(&config).unwrap_or_else(|e| {
+        error!("TLS setup failed: {}", e);
+        process::exit(1);
+    });
+    let reloader = Reloader::new(args, config.clone()).unwrap_or_else(|e| {
+        error!("Invalid configuration: {}", e);
+        process::exit(1);
+    });
+    let state = reloader.state().clone();
+    let canonical_static_dir = state.static_dir.clone();
+    let in_flight = InFlight::default();
+    let shutdown = shutdown::on_signal(
+        in_flight.clone(),
+        state.shutdown.clone(),
+        config.drain_timeout,
+    );
+    let app = reloader.router().layer(axum_middleware::from_fn_with_state(
+        in_flight.clone(),
+        shutdown::track_in_flight,
+    ));
 
     info!("Serving static files from: {:?}", canonical_static_dir);
-    info!(
-        "Proxying {}/* to: {}{}/",
-        args.api_path, state.api_base_url, args.api_path
-    );
-    info!("Server running on: http://{}", args.bind);
+    if let Some(fallback) = &state.spa_fallback {
+        info!("SPA fallback document: {:?}", fallback);
+    }
+    if let Some(compression) = &state.compression {
+        let algorithms: Vec<_> = compression.algorithms.iter().map(|a| a.as_str()).collect();
+        info!(
+            "Compressing responses ≥ {}B with {}",
+            compression.min_size,
+            algorithms.join(", ")
+        );
+    }
+    if state.live_reload.is_some() {
+        info!("Live reload: watching {:?}", canonical_static_dir);
+    }
+    if state.metrics.is_some() {
+        info!("Metrics: {}", METRICS_PATH);
+    }
+    if let Some(endpoint) = &config.otlp_endpoint {
+        info!("Exporting traces to: {}", endpoint);
+    }
+    if let Some(access_log) = &config.access_log {
+        info!("Access log: {}", access_log);
+    }
+    for route in &state.routes {
+        info!("Proxying {} ({})", route, route.label);
+        for rule in &route.rewrites {
+            info!("  rewrite {}", rule);
+        }
+        for (name, value) in &route.headers {
+            info!("  header {}: {:?}", name, value);
+        }
+    }
+
+    tokio::spawn(reload::run(reloader));
+
+    let listener = tokio::net::TcpListener::bind(config.bind).await.unwrap();
+    match tls_config {
+        Some(tls) => {
+            info!("Server running on: https://{}", config.bind);
+            let listener = TlsListener::new(listener, Arc::new(tls)).unwrap();
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<ClientAddr>(),
+            )
+            .with_graceful_shutdown(shutdown)
+            .await
+            .unwrap();
+        }
+        None => {
+            info!("Server running on: http://{}", config.bind);
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<ClientAddr>(),
+            )
+            .with_graceful_shutdown(shutdown)
+            .await
+            .unwrap();
+        }
+    }
+    // Upgraded connections are not part of the server's own drain
+    in_flight.drained().await;
+    info!("All requests drained, bye");
+}
+
+/// Loads the TLS configuration requested by the config, if any
+fn tls_config(config: &Config) -> Result<Option<ServerConfig>, String> {
+    let files = match &config.tls {
+        Some(TlsMode::SelfSigned) => {
+            let cache_dir = tls::default_cache_dir()?;
+            info!("Using local CA: {:?}", cache_dir.join("ca.pem"));
+            tls::self_signed(&cache_dir, config.bind.ip())?
+        }
+        Some(TlsMode::Files(files)) => files.clone(),
+        None => return Ok(None),
+    };
 
-    axum::serve(tokio::net::TcpListener::bind(args.bind).await.unwrap(), app)
-        .await
-        .unwrap();
+    let certs = tls::load_certs(&files.cert)?;
+    info!("TLS certificate: {:?}", files.cert);
+    info!("TLS fingerprint (SHA-256): {}", tls::fingerprint(&certs[0]));
+    tls::server_config(certs, &files.key).map(Some)
 }
@@ -0,0 +1,417 @@
+//! Prometheus metrics for proxy and static traffic.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::body::{Frame, SizeHint};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::live_reload::LIVE_RELOAD_PATH;
+use crate::routes::find_route;
+use crate::shutdown::{InFlight, InFlightGuard};
+use crate::state::AppState;
+use crate::upstream::ErrorKind;
+
+/// Path of the Prometheus scrape endpoint
+pub const METRICS_PATH: &str = "/__local/metrics";
+
+/// Paths of local-rs' own endpoints, all under the reserved `/__local/`
+/// prefix, which are left out of the metrics
+const INTERNAL_PATHS: [&str; 2] = [METRICS_PATH, LIVE_RELOAD_PATH];
+
+/// Latency histogram bucket bounds in seconds (the Prometheus client defaults)
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Label of requests served from the static dir
+const STATIC_ROUTE: &str = "STATIC";
+
+/// Whether a request was served from disk or proxied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Static,
+    Proxy,
+}
+
+impl Kind {
+    const ALL: [Kind; 2] = [Kind::Static, Kind::Proxy];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Static => "static",
+            Kind::Proxy => "proxy",
+        }
+    }
+}
+
+/// Counters shared by every request, kept across config reloads
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Requests by route, method and status
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    /// Time to response headers by kind and route
+    durations: Mutex<BTreeMap<(Kind, String), Histogram>>,
+    /// Failed upstream requests by route and error kind
+    upstream_errors: Mutex<BTreeMap<(String, &'static str), u64>>,
+    /// Request body bytes by kind, indexed like `Kind::ALL`
+    bytes_received: [Arc<AtomicU64>; 2],
+    /// Response body bytes by kind, indexed like `Kind::ALL`
+    bytes_sent: [Arc<AtomicU64>; 2],
+    /// Requests whose response body is not done yet, by kind
+    in_flight: [InFlight; 2],
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let secs = value.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| secs <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+}
+
+impl Metrics {
+    /// Counts a failed upstream request for the route labeled `route`
+    pub fn upstream_error(&self, route: &str, kind: ErrorKind) {
+        *self
+            .inner
+            .upstream_errors
+            .lock()
+            .unwrap()
+            .entry((route.to_string(), kind.as_str()))
+            .or_default() += 1;
+    }
+
+    fn record(
+        &self,
+        kind: Kind,
+        route: &str,
+        method: &Method,
+        status: StatusCode,
+        latency: Duration,
+    ) {
+        *self
+            .inner
+            .requests
+            .lock()
+            .unwrap()
+            .entry((route.to_string(), method.to_string(), status.as_u16()))
+            .or_default() += 1;
+        self.inner
+            .durations
+            .lock()
+            .unwrap()
+            .entry((kind, route.to_string()))
+            .or_default()
+            .observe(latency);
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        family(
+            &mut out,
+            "local_rs_requests_total",
+            "counter",
+            "Requests handled, by route, method and status",
+        );
+        for ((route, method, status), count) in self.inner.requests.lock().unwrap().iter() {
+            let labels = labels(&[
+                ("route", route),
+                ("method", method),
+                ("status", &status.to_string()),
+            ]);
+            writeln!(out, "local_rs_requests_total{} {}", labels, count).unwrap();
+        }
+
+        family(
+            &mut out,
+            "local_rs_request_duration_seconds",
+            "histogram",
+            "Time until the response headers were ready, static vs. proxied",
+        );
+        for ((kind, route), histogram) in self.inner.durations.lock().unwrap().iter() {
+            let name = "local_rs_request_duration_seconds";
+            let base = [("kind", kind.as_str()), ("route", route.as_str())];
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                let labels = labels(&[base[0], base[1], ("le", &le)]);
+                writeln!(out, "{}_bucket{} {}", name, labels, cumulative).unwrap();
+            }
+            let labels_inf = labels(&[base[0], base[1], ("le", "+Inf")]);
+            writeln!(out, "{}_bucket{} {}", name, labels_inf, histogram.count).unwrap();
+            writeln!(out, "{}_sum{} {}", name, labels(&base), histogram.sum).unwrap();
+            writeln!(out, "{}_count{} {}", name, labels(&base), histogram.count).unwrap();
+        }
+
+        family(
+            &mut out,
+            "local_rs_upstream_errors_total",
+            "counter",
+            "Failed upstream requests, by route and error kind",
+        );
+        for ((route, kind), count) in self.inner.upstream_errors.lock().unwrap().iter() {
+            let labels = labels(&[("route", route), ("kind", kind)]);
+            writeln!(out, "local_rs_upstream_errors_total{} {}", labels, count).unwrap();
+        }
+
+        for (name, help, counters) in [
+            (
+                "local_rs_received_bytes_total",
+                "Request body bytes received from clients",
+                &self.inner.bytes_received,
+            ),
+            (
+                "local_rs_sent_bytes_total",
+                "Response body bytes sent to clients",
+                &self.inner.bytes_sent,
+            ),
+        ] {
+            family(&mut out, name, "counter", help);
+            for (kind, counter) in Kind::ALL.iter().zip(counters) {
+                let labels = labels(&[("kind", kind.as_str())]);
+                let bytes = counter.load(Ordering::Relaxed);
+                writeln!(out, "{}{} {}", name, labels, bytes).unwrap();
+            }
+        }
+
+        family(
+            &mut out,
+            "local_rs_in_flight_requests",
+            "gauge",
+            "Requests whose response has not been fully sent yet",
+        );
+        for (kind, in_flight) in Kind::ALL.iter().zip(&self.inner.in_flight) {
+            let labels = labels(&[("kind", kind.as_str())]);
+            writeln!(
+                out,
+                "local_rs_in_flight_requests{} {}",
+                labels,
+                in_flight.count()
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+/// Writes the `# HELP` and `# TYPE` lines of a metric family
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Formats a label set, e.g. `{route="API",status="200"}`
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<_> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+/// Middleware recording each request in the metrics, when they are on
+///
+/// Body bytes are counted as they stream, and a request stays in flight until
+/// its response body is done.
+pub async fn track_metrics(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(metrics) = state.metrics.clone() else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path();
+    if INTERNAL_PATHS.contains(&path) {
+        return next.run(request).await;
+    }
+    let (kind, route) = match find_route(&state.routes, path) {
+        Some(route) => (Kind::Proxy, route.label.clone()),
+        None => (Kind::Static, STATIC_ROUTE.to_string()),
+    };
+    let index = kind as usize;
+    let method = request.method().clone();
+    let guard = metrics.inner.in_flight[index].enter();
+    let start = Instant::now();
+
+    let request = request.map(|body| {
+        Body::new(MeteredBody {
+            body,
+            bytes: metrics.inner.bytes_received[index].clone(),
+            _guard: None,
+        })
+    });
+    let response = next.run(request).await;
+    metrics.record(kind, &route, &method, response.status(), start.elapsed());
+
+    response.map(|body| {
+        Body::new(MeteredBody {
+            body,
+            bytes: metrics.inner.bytes_sent[index].clone(),
+            _guard: Some(guard),
+        })
+    })
+}
+
+/// Serves the metrics in the Prometheus text format
+pub async fn metrics_endpoint(State(state): State<Arc<AppState>>) -> Response {
+    let body = state
+        .metrics
+        .as_ref()
+        .map(Metrics::render)
+        .unwrap_or_default();
+    let mut response = body.into_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+    );
+    response
+}
+
+/// A body that counts the data bytes passing through it
+struct MeteredBody {
+    body: Body,
+    bytes: Arc<AtomicU64>,
+    _guard: Option<InFlightGuard>,
+}
+
+impl HttpBody for MeteredBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+        {
+            self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counters_and_histograms() {
+        let metrics = Metrics::default();
+        metrics.record(
+            Kind::Proxy,
+            "API",
+            &Method::GET,
+            StatusCode::OK,
+            Duration::from_millis(30),
+        );
+        metrics.record(
+            Kind::Proxy,
+            "API",
+            &Method::GET,
+            StatusCode::OK,
+            Duration::from_secs(20),
+        );
+        metrics.upstream_error("API", ErrorKind::Refused);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE local_rs_requests_total counter\n"));
+        assert!(
+            text.contains(
+                "local_rs_requests_total{route=\"API\",method=\"GET\",status=\"200\"} 2\n"
+            )
+        );
+        let bucket = |le: &str, count: u64| {
+            format!(
+                "local_rs_request_duration_seconds_bucket{{kind=\"proxy\",route=\"API\",le=\"{}\"}} {}\n",
+                le, count
+            )
+        };
+        assert!(text.contains(&bucket("0.025", 0)));
+        assert!(text.contains(&bucket("0.05", 1)));
+        assert!(text.contains(&bucket("10", 1)));
+        assert!(text.contains(&bucket("+Inf", 2)));
+        assert!(
+            text.contains(
+                "local_rs_request_duration_seconds_count{kind=\"proxy\",route=\"API\"} 2\n"
+            )
+        );
+        assert!(
+            text.contains("local_rs_upstream_errors_total{route=\"API\",kind=\"refused\"} 1\n")
+        );
+        assert!(text.contains("local_rs_in_flight_requests{kind=\"static\"} 0\n"));
+    }
+
+    #[test]
+    fn test_labels_are_escaped() {
+        assert_eq!(
+            labels(&[("route", "a\"b\\c"), ("method", "GET")]),
+            "{route=\"a\\\"b\\\\c\",method=\"GET\"}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metered_body_counts_bytes() {
+        let bytes = Arc::new(AtomicU64::new(0));
+        let body = MeteredBody {
+            body: Body::from("hello"),
+            bytes: bytes.clone(),
+            _guard: None,
+        };
+        axum::body::to_bytes(Body::new(body), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes.load(Ordering::Relaxed), 5);
+    }
+}
@@ -1,21 +1,77 @@
 //! Request logging middleware.
 
-use axum::{body::Body, http::Request, middleware::Next, response::Response};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
 use nanoid::nanoid;
-use std::time::Instant;
+use std::{fmt, str::FromStr, sync::Arc, time::Instant};
 use tracing::info;
 
 use crate::colors::colored_id;
+use crate::state::AppState;
+
+/// Longest client-supplied request ID that is reused rather than replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Header carrying request IDs between clients, local-rs and backends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestIdHeader(pub HeaderName);
+
+impl Default for RequestIdHeader {
+    fn default() -> Self {
+        RequestIdHeader(HeaderName::from_static("x-request-id"))
+    }
+}
+
+impl fmt::Display for RequestIdHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0.as_str())
+    }
+}
+
+impl FromStr for RequestIdHeader {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        HeaderName::from_str(name.trim())
+            .map(RequestIdHeader)
+            .map_err(|_| format!("invalid request ID header name '{}'", name))
+    }
+}
+
+impl RequestIdHeader {
+    /// The ID the client sent, if it is short printable ASCII, or a fresh one
+    pub fn id_for(&self, headers: &HeaderMap) -> String {
+        headers
+            .get(&self.0)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LEN
+                    && id.bytes().all(|b| b.is_ascii_graphic())
+            })
+            .map_or_else(|| nanoid!(5), str::to_string)
+    }
+}
 
 /// Middleware that logs incoming requests and assigns them unique colored IDs
 ///
 /// This middleware:
-/// 1. Generates a short nanoid for each request
+/// 1. Reuses the client's request ID header, or generates a short nanoid
 /// 2. Records the start time for latency calculation
 /// 3. Logs the initial request with colored ID
 /// 4. Stores the ID and start time in request extensions for downstream handlers
-pub async fn log_requests(mut req: Request<Body>, next: Next) -> Response {
-    let id = nanoid!(5);
+/// 5. Returns the ID to the client in the same header
+pub async fn log_requests(
+    State(state): State<Arc<AppState>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let id = state.request_id_header.id_for(req.headers());
     let method = req.method().clone();
     let uri = req.uri().clone();
 
@@ -23,5 +79,41 @@ pub async fn log_requests(mut req: Request<Body>, next: Next) -> Response {
     req.extensions_mut().insert(Instant::now());
 
     info!("{} → {} {}", colored_id(&id), method, uri.path());
-    next.run(req).await
+    let mut response = next.run(req).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(state.request_id_header.0.clone(), value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuses_client_request_id() {
+        let header = RequestIdHeader::default();
+        let mut headers = HeaderMap::new();
+        assert_eq!(header.id_for(&headers).len(), 5);
+
+        headers.insert("x-request-id", HeaderValue::from_static("req-42"));
+        assert_eq!(header.id_for(&headers), "req-42");
+
+        let custom: RequestIdHeader = "X-Correlation-Id".parse().unwrap();
+        assert_eq!(custom.to_string(), "x-correlation-id");
+        assert_eq!(custom.id_for(&headers).len(), 5);
+    }
+
+    #[test]
+    fn test_replaces_unusable_request_ids() {
+        let header = RequestIdHeader::default();
+        for bad in ["", "has space", &"x".repeat(MAX_REQUEST_ID_LEN + 1)] {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-request-id", HeaderValue::from_str(bad).unwrap());
+            assert_eq!(header.id_for(&headers).len(), 5, "{:?}", bad);
+        }
+        assert!("bad header".parse::<RequestIdHeader>().is_err());
+    }
 }
@@ -0,0 +1,312 @@
+//! Export of finished spans to an OpenTelemetry collector over OTLP/HTTP, JSON-encoded.
+
+use serde_json::{Value, json};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::warn;
+
+/// `service.name` reported for every span
+pub const SERVICE_NAME: &str = "local-rs";
+
+/// Most spans sent in one export request
+const MAX_BATCH: usize = 512;
+
+/// How long the first span of a batch waits for company before being sent
+const BATCH_DELAY: Duration = Duration::from_millis(200);
+
+/// Most spans waiting for export; spans beyond it are dropped
+const QUEUE_CAPACITY: usize = 8192;
+
+/// How long a collector gets to accept a batch
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Which side of an HTTP exchange a span describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    /// A request served by local-rs
+    Server,
+    /// A request local-rs sent to a backend
+    Client,
+}
+
+/// A span attribute value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttributeValue {
+    String(String),
+    Int(i64),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        AttributeValue::String(value.to_string())
+    }
+}
+
+impl From<String> for AttributeValue {
+    fn from(value: String) -> Self {
+        AttributeValue::String(value)
+    }
+}
+
+impl From<u16> for AttributeValue {
+    fn from(value: u16) -> Self {
+        AttributeValue::Int(value.into())
+    }
+}
+
+/// A finished span, ready for export
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub parent_span_id: Option<u64>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, AttributeValue)>,
+    /// Set when the span failed, as its status message
+    pub error: Option<String>,
+}
+
+/// Sends finished spans to a collector in the background, in batches
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    endpoint: String,
+    spans: mpsc::Sender<Span>,
+    /// Spans dropped on a full queue since the export task last reported them
+    dropped: Arc<AtomicU64>,
+}
+
+impl Exporter {
+    /// Starts exporting to `endpoint`, the collector's base URL (e.g.
+    /// `http://127.0.0.1:4318`); spans are posted to its `/v1/traces`
+    ///
+    /// Must be called from within a Tokio runtime. The background task
+    /// flushes and ends once every clone of the exporter is dropped.
+    pub fn new(endpoint: &str) -> Self {
+        let (spans, pending) = mpsc::channel(QUEUE_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(run(traces_url(endpoint), pending, dropped.clone()));
+        Exporter {
+            endpoint: endpoint.to_string(),
+            spans,
+            dropped,
+        }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Queues a finished span for export
+    ///
+    /// Never waits: when the collector falls behind and the queue is full,
+    /// the span is dropped and counted, and the export task warns about it.
+    pub fn export(&self, span: Span) {
+        if let Err(TrySendError::Full(_)) = self.spans.try_send(span) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The traces URL for a collector base URL, as OTLP exporters build it
+fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint)
+    }
+}
+
+async fn run(url: String, mut pending: mpsc::Receiver<Span>, dropped: Arc<AtomicU64>) {
+    let client = reqwest::Client::builder()
+        .timeout(EXPORT_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    while let Some(first) = pending.recv().await {
+        tokio::time::sleep(BATCH_DELAY).await;
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH
+            && let Ok(span) = pending.try_recv()
+        {
+            batch.push(span);
+        }
+        // Reported at most once per batch, so a stalled collector cannot
+        // flood the log
+        let dropped = dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            warn!("Dropped {} spans for {}: export queue full", dropped, url);
+        }
+
+        let result = client
+            .post(&url)
+            .header("content-type", "application/json")
+            .body(encode(&batch).to_string())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            warn!("Exporting {} spans to {} failed: {}", batch.len(), url, e);
+        }
+    }
+}
+
+/// Encodes spans as an OTLP `ExportTraceServiceRequest` in its JSON mapping
+///
+/// IDs are hex strings and 64-bit integers are decimal strings, as the
+/// OTLP/JSON spec requires.
+pub fn encode(spans: &[Span]) -> Value {
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let mut encoded = json!({
+                "traceId": format!("{:032x}", span.trace_id),
+                "spanId": format!("{:016x}", span.span_id),
+                "name": span.name,
+                "kind": match span.kind {
+                    SpanKind::Server => 2,
+                    SpanKind::Client => 3,
+                },
+                "startTimeUnixNano": unix_nanos(span.start),
+                "endTimeUnixNano": unix_nanos(span.end),
+                "attributes": span
+                    .attributes
+                    .iter()
+                    .map(|(key, value)| attribute(key, value))
+                    .collect::<Vec<_>>(),
+                "status": match &span.error {
+                    Some(message) => json!({ "code": 2, "message": message }),
+                    None => json!({}),
+                },
+            });
+            if let Some(parent) = span.parent_span_id {
+                encoded["parentSpanId"] = format!("{:016x}", parent).into();
+            }
+            encoded
+        })
+        .collect();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", &SERVICE_NAME.into())],
+            },
+            "scopeSpans": [{
+                "scope": { "name": SERVICE_NAME, "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn attribute(key: &str, value: &AttributeValue) -> Value {
+    let value = match value {
+        AttributeValue::String(value) => json!({ "stringValue": value }),
+        AttributeValue::Int(value) => json!({ "intValue": value.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traces_url() {
+        assert_eq!(
+            traces_url("http://127.0.0.1:4318"),
+            "http://127.0.0.1:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("http://collector:4318/"),
+            "http://collector:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("http://collector/v1/traces"),
+            "http://collector/v1/traces"
+        );
+    }
+
+    fn span(start: SystemTime) -> Span {
+        Span {
+            trace_id: 1,
+            span_id: 1,
+            parent_span_id: None,
+            name: "GET".to_string(),
+            kind: SpanKind::Server,
+            start,
+            end: start,
+            attributes: Vec::new(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_export_drops_spans_when_queue_is_full() {
+        let (spans, mut pending) = mpsc::channel(2);
+        let exporter = Exporter {
+            endpoint: "http://127.0.0.1:4318".to_string(),
+            spans,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        for _ in 0..5 {
+            exporter.export(span(UNIX_EPOCH));
+        }
+        assert_eq!(exporter.dropped.load(Ordering::Relaxed), 3);
+        assert!(pending.try_recv().is_ok());
+        assert!(pending.try_recv().is_ok());
+        assert!(pending.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_encode_span() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_791_538_205);
+        let span = Span {
+            trace_id: 0x4bf92f3577b34da6a3ce929d0e0e4736,
+            span_id: 0x00f067aa0ba902b7,
+            parent_span_id: Some(1),
+            name: "GET".to_string(),
+            kind: SpanKind::Client,
+            start,
+            end: start + Duration::from_millis(12),
+            attributes: vec![
+                ("http.request.method", "GET".into()),
+                ("http.response.status_code", 502u16.into()),
+            ],
+            error: Some("connection refused".to_string()),
+        };
+        let json = encode(&[span]);
+
+        let resource = &json["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "local-rs"
+        );
+        let encoded = &resource["scopeSpans"][0]["spans"][0];
+        assert_eq!(encoded["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(encoded["spanId"], "00f067aa0ba902b7");
+        assert_eq!(encoded["parentSpanId"], "0000000000000001");
+        assert_eq!(encoded["kind"], 3);
+        assert_eq!(encoded["startTimeUnixNano"], "1791538205000000000");
+        assert_eq!(encoded["endTimeUnixNano"], "1791538205012000000");
+        assert_eq!(encoded["attributes"][1]["key"], "http.response.status_code");
+        assert_eq!(encoded["attributes"][1]["value"]["intValue"], "502");
+        assert_eq!(encoded["status"]["code"], 2);
+        assert_eq!(encoded["status"]["message"], "connection refused");
+    }
+}
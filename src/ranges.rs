@@ -0,0 +1,209 @@
+//! HTTP Range requests (RFC 9110 §14) for static files.
+
+/// More ranges than this in one request are ignored and the full file is served
+pub const MAX_RANGES: usize = 16;
+
+/// An inclusive byte range that lies within the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    /// First byte of the range
+    pub start: u64,
+    /// Last byte of the range, inclusive
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// Number of bytes covered by the range
+    pub fn byte_count(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// `Content-Range` value for this range of a file of `size` bytes
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+/// How a `Range` header applies to a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable range, serve the whole file with 200
+    Full,
+    /// Serve these ranges with 206
+    Partial(Vec<ByteRange>),
+    /// None of the ranges overlap the file, answer 416
+    Unsatisfiable,
+}
+
+/// Resolves a `Range` header against a file of `size` bytes
+///
+/// Malformed headers, units other than `bytes` and requests for more than
+/// [`MAX_RANGES`] ranges are ignored, as RFC 9110 allows.
+pub fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let Some((unit, specs)) = header.split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Full;
+    }
+
+    let specs: Vec<&str> = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::with_capacity(specs.len());
+    for spec in specs {
+        match parse_spec(spec, size) {
+            Some(Some(range)) => ranges.push(range),
+            Some(None) => {}
+            None => return RangeRequest::Full,
+        }
+    }
+
+    if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(ranges)
+    }
+}
+
+/// Parses one range spec: `None` if malformed, `Some(None)` if outside the file
+fn parse_spec(spec: &str, size: u64) -> Option<Option<ByteRange>> {
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        // Suffix range: the last `n` bytes
+        let suffix: u64 = parse_digits(last)?;
+        return Some((suffix > 0 && size > 0).then(|| ByteRange {
+            start: size.saturating_sub(suffix),
+            end: size - 1,
+        }));
+    }
+
+    let start = parse_digits(first)?;
+    let end = match last {
+        "" => u64::MAX,
+        last => parse_digits(last)?,
+    };
+    if end < start {
+        return None;
+    }
+
+    Some((start < size).then(|| ByteRange {
+        start,
+        end: end.min(size - 1),
+    }))
+}
+
+fn parse_digits(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Opening delimiter and headers of one `multipart/byteranges` part
+pub fn part_header(boundary: &str, content_type: &str, range: &ByteRange, size: u64) -> String {
+    format!(
+        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+        boundary,
+        content_type,
+        range.content_range(size)
+    )
+}
+
+/// Closing delimiter of a `multipart/byteranges` body
+pub fn closing_delimiter(boundary: &str) -> String {
+    format!("\r\n--{}--\r\n", boundary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse_single_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeRequest::Partial(vec![range(0, 99)])
+        );
+        assert_eq!(
+            parse_range("bytes=900-", 1000),
+            RangeRequest::Partial(vec![range(900, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeRequest::Partial(vec![range(900, 999)])
+        );
+        // Ends past the file are clamped, long suffixes cover the whole file
+        assert_eq!(
+            parse_range("bytes=990-2000", 1000),
+            RangeRequest::Partial(vec![range(990, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeRequest::Partial(vec![range(0, 999)])
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_ranges() {
+        assert_eq!(
+            parse_range("bytes=0-9, 20-29,, -5", 100),
+            RangeRequest::Partial(vec![range(0, 9), range(20, 29), range(95, 99)])
+        );
+        // Unsatisfiable specs are dropped as long as one range remains
+        assert_eq!(
+            parse_range("bytes=0-9,500-600", 100),
+            RangeRequest::Partial(vec![range(0, 9)])
+        );
+    }
+
+    #[test]
+    fn test_parse_unsatisfiable() {
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+    }
+
+    #[test]
+    fn test_parse_ignores_invalid_headers() {
+        for header in [
+            "bytes",
+            "items=0-9",
+            "bytes=",
+            "bytes=9-0",
+            "bytes=a-b",
+            "bytes=0-9,x",
+            "bytes=+1-2",
+        ] {
+            assert_eq!(parse_range(header, 100), RangeRequest::Full, "{}", header);
+        }
+
+        let too_many = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse_range(&too_many, 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_multipart_framing() {
+        let header = part_header("b0undary", "text/plain", &range(0, 4), 10);
+        assert_eq!(
+            header,
+            "\r\n--b0undary\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-4/10\r\n\r\n"
+        );
+        assert_eq!(closing_delimiter("b0undary"), "\r\n--b0undary--\r\n");
+    }
+}
@@ -0,0 +1,247 @@
+//! Hot reloading of the configuration without dropping connections.
+//!
+//! The router is swapped atomically: requests that already started keep the
+//! router (and state) they were dispatched to, so in-flight requests and open
+//! WebSocket tunnels survive a reload.
+
+use arc_swap::ArcSwap;
+use axum::{Router, extract::Request};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use owo_colors::OwoColorize;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use tower::{ServiceExt, service_fn};
+use tracing::{error, info, warn};
+
+use crate::cli::Cli;
+use crate::config::{Config, config_path};
+use crate::live_reload;
+use crate::router::build_router;
+use crate::state::AppState;
+
+/// How long config changes must settle before reloading (editors write in steps)
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A router that can be replaced while the server is running
+#[derive(Clone)]
+pub struct SharedRouter {
+    current: Arc<ArcSwap<Router>>,
+}
+
+impl SharedRouter {
+    pub fn new(router: Router) -> Self {
+        SharedRouter {
+            current: Arc::new(ArcSwap::from_pointee(router)),
+        }
+    }
+
+    /// Routes new requests to `router` from now on
+    pub fn swap(&self, router: Router) {
+        self.current.store(Arc::new(router));
+    }
+
+    /// A router handing each request to whichever router is current when it arrives
+    pub fn router(&self) -> Router {
+        let current = self.current.clone();
+        Router::new().fallback_service(service_fn(move |request: Request| {
+            let router = Router::clone(&current.load());
+            router.oneshot(request)
+        }))
+    }
+}
+
+/// Owns the running configuration and rebuilds it on demand
+pub struct Reloader {
+    cli: Cli,
+    config: Config,
+    state: Arc<AppState>,
+    router: SharedRouter,
+    /// Static dir watcher feeding live reload; dropping it stops watching
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl Reloader {
+    /// Builds the initial state and router for `config`
+    pub fn new(cli: Cli, config: Config) -> Result<Self, String> {
+        let state = Arc::new(config.app_state(None)?);
+        let watcher = watch_static_dir(&state)?;
+        Ok(Reloader {
+            cli,
+            config,
+            router: SharedRouter::new(build_router(state.clone())),
+            state,
+            watcher,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    /// The router to serve; it follows every successful reload
+    pub fn router(&self) -> Router {
+        self.router.router()
+    }
+
+    /// Re-reads the config file and flags, and switches over if they are valid
+    ///
+    /// On error nothing changes. The bind address, TLS settings, drain
+    /// timeout and log format are set up at startup, so changes to them need
+    /// a restart.
+    pub fn reload(&mut self) -> Result<(), String> {
+        let mut config = Config::load(&self.cli)?;
+        if config.bind != self.config.bind
+            || config.tls != self.config.tls
+            || config.drain_timeout != self.config.drain_timeout
+            || config.log_format != self.config.log_format
+        {
+            warn!(
+                "Bind address, TLS, drain timeout and log format changes take effect after a restart"
+            );
+            config.bind = self.config.bind;
+            config.tls = self.config.tls.clone();
+            config.drain_timeout = self.config.drain_timeout;
+            config.log_format = self.config.log_format;
+        }
+
+        let mut state = config.app_state(Some(&self.state))?;
+        state.clients = self.state.clients.clone();
+        state.shutdown = self.state.shutdown.clone();
+        // Keep connected browsers subscribed across reloads
+        if let (Some(new), Some(old)) = (&mut state.live_reload, &self.state.live_reload) {
+            *new = old.clone();
+        }
+        // And keep counting where the previous config left off
+        if let (Some(new), Some(old)) = (&mut state.metrics, &self.state.metrics) {
+            *new = old.clone();
+        }
+        let state = Arc::new(state);
+        let watcher = if state.static_dir == self.state.static_dir
+            && state.live_reload.is_some() == self.state.live_reload.is_some()
+        {
+            self.watcher.take()
+        } else {
+            watch_static_dir(&state)?
+        };
+
+        let changes = config.diff(&self.config);
+        self.router.swap(build_router(state.clone()));
+        self.config = config;
+        self.state = state;
+        self.watcher = watcher;
+
+        if changes.is_empty() {
+            info!("{} config unchanged", "RELOAD".magenta());
+        }
+        for change in changes {
+            info!("{} {}", "RELOAD".magenta(), change);
+        }
+        Ok(())
+    }
+}
+
+/// Starts the live reload watcher for `state`, if live reload is on
+fn watch_static_dir(state: &AppState) -> Result<Option<RecommendedWatcher>, String> {
+    state
+        .live_reload
+        .clone()
+        .map(|live_reload| live_reload::watch(&state.static_dir, live_reload))
+        .transpose()
+        .map_err(|e| format!("cannot watch {:?}: {}", state.static_dir, e))
+}
+
+/// Reloads on SIGHUP and whenever the config file changes, and reopens the
+/// access log on SIGUSR1, until the server exits
+pub async fn run(mut reloader: Reloader) {
+    let (triggers, mut pending) = mpsc::unbounded_channel();
+    let (reopen_triggers, mut reopens) = mpsc::unbounded_channel::<()>();
+
+    // Dropping the watcher stops it, so it lives as long as this task
+    let _watcher = config_path(&reloader.cli).and_then(|path| {
+        watch_config(&path, triggers.clone())
+            .map_err(|e| error!("Cannot watch config {:?}: {}", path, e))
+            .ok()
+    });
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::hangup()) {
+            Ok(mut hangups) => {
+                let triggers = triggers.clone();
+                tokio::spawn(async move {
+                    while hangups.recv().await.is_some() {
+                        info!("{} SIGHUP received", "RELOAD".magenta());
+                        let _ = triggers.send(());
+                    }
+                });
+            }
+            Err(e) => error!("Cannot listen for SIGHUP: {}", e),
+        }
+        // logrotate moves the file away, then asks for a fresh one
+        match signal(SignalKind::user_defined1()) {
+            Ok(mut usr1s) => {
+                tokio::spawn(async move {
+                    while usr1s.recv().await.is_some() {
+                        let _ = reopen_triggers.send(());
+                    }
+                });
+            }
+            Err(e) => error!("Cannot listen for SIGUSR1: {}", e),
+        }
+    }
+    #[cfg(not(unix))]
+    drop(reopen_triggers);
+
+    loop {
+        tokio::select! {
+            Some(()) = pending.recv() => {
+                tokio::time::sleep(DEBOUNCE).await;
+                while pending.try_recv().is_ok() {}
+
+                if let Err(e) = reloader.reload() {
+                    error!("Config reload failed, keeping the current config: {}", e);
+                }
+            }
+            Some(()) = reopens.recv() => {
+                if let Some(log) = &reloader.state().access_log_file {
+                    log.file.reopen();
+                }
+            }
+            else => break,
+        }
+    }
+}
+
+/// Watches the config file's directory for changes to the file itself
+///
+/// Editors often save by replacing the file, which a watch on the file alone
+/// would lose track of.
+fn watch_config(
+    path: &Path,
+    triggers: mpsc::UnboundedSender<()>,
+) -> notify::Result<RecommendedWatcher> {
+    let path = path.canonicalize()?;
+    let dir = path.parent().map(PathBuf::from).unwrap_or_default();
+    let mut watcher =
+        notify::recommended_watcher(move |result: notify::Result<notify::Event>| match result {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                if event.paths.iter().any(|changed| changed == &path) {
+                    let _ = triggers.send(());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!("Config watcher error: {}", e),
+        })?;
+
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
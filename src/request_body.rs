@@ -0,0 +1,115 @@
+//! Streaming client request bodies to upstreams, with an optional size limit.
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{HeaderMap, header},
+};
+use futures_util::StreamExt;
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// Whether the `Content-Length` a client declared is over `limit`
+pub fn declared_too_large(headers: &HeaderMap, limit: u64) -> bool {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok())
+        .is_some_and(|length| length > limit)
+}
+
+/// Records whether a streamed body went over its size limit
+#[derive(Debug, Clone, Default)]
+pub struct Overflow(Arc<AtomicBool>);
+
+impl Overflow {
+    pub fn occurred(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Turns a client request body into an upstream body, chunk by chunk
+///
+/// Nothing is buffered: the backend sees the upload while it's still arriving.
+/// With a `limit`, the stream fails once more than `limit` bytes came in, and
+/// the returned [`Overflow`] tells that failure apart from a backend error.
+/// Empty bodies are sent as none at all so that bodiless requests don't
+/// become chunked ones.
+pub fn upstream_body(body: Body, limit: Option<u64>) -> (Option<reqwest::Body>, Overflow) {
+    let overflow = Overflow::default();
+    if body.size_hint().exact() == Some(0) {
+        return (None, overflow);
+    }
+    let Some(limit) = limit else {
+        return (
+            Some(reqwest::Body::wrap_stream(body.into_data_stream())),
+            overflow,
+        );
+    };
+
+    let flag = overflow.clone();
+    let mut received = 0u64;
+    let stream = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(io::Error::other)?;
+        received += chunk.len() as u64;
+        if received > limit {
+            flag.0.store(true, Ordering::SeqCst);
+            return Err(io::Error::other(format!(
+                "request body exceeds {} bytes",
+                limit
+            )));
+        }
+        Ok(chunk)
+    });
+    (Some(reqwest::Body::wrap_stream(stream)), overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use futures_util::stream;
+
+    fn chunked(chunks: &[&'static str]) -> Body {
+        let chunks: Vec<Result<_, io::Error>> = chunks.iter().map(|c| Ok(*c)).collect();
+        Body::from_stream(stream::iter(chunks))
+    }
+
+    #[test]
+    fn test_declared_too_large() {
+        let mut headers = HeaderMap::new();
+        assert!(!declared_too_large(&headers, 10));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("10"));
+        assert!(!declared_too_large(&headers, 10));
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("11"));
+        assert!(declared_too_large(&headers, 10));
+    }
+
+    #[tokio::test]
+    async fn test_upstream_body_within_limit() {
+        let (body, overflow) = upstream_body(chunked(&["hello ", "world"]), Some(11));
+        let bytes = axum::body::to_bytes(Body::new(body.unwrap()), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes, "hello world");
+        assert!(!overflow.occurred());
+    }
+
+    #[tokio::test]
+    async fn test_upstream_body_over_limit() {
+        let (body, overflow) = upstream_body(chunked(&["hello ", "world"]), Some(8));
+        let bytes = axum::body::to_bytes(Body::new(body.unwrap()), usize::MAX).await;
+        assert!(bytes.is_err());
+        assert!(overflow.occurred());
+    }
+
+    #[test]
+    fn test_empty_body_is_not_streamed() {
+        let (body, _) = upstream_body(Body::empty(), None);
+        assert!(body.is_none());
+    }
+}
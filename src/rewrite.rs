@@ -0,0 +1,183 @@
+//! Path rewrite rules applied to proxied requests before they reach the backend.
+
+use regex::Regex;
+use std::{fmt, str::FromStr};
+
+/// A rule that transforms the path forwarded to the backend
+///
+/// Rules are matched against the upstream path (after the route's optional
+/// prefix stripping); the first matching rule of a route wins.
+#[derive(Debug, Clone)]
+pub enum RewriteRule {
+    /// Removes a leading path prefix (`/api/v1/users` → `/users`)
+    StripPrefix(String),
+    /// Swaps a leading path prefix for another (`/api/v1/users` → `/v2/users`)
+    ReplacePrefix { from: String, to: String },
+    /// Rewrites with a regex, `$1`-style captures allowed in the replacement
+    Regex { pattern: Regex, replacement: String },
+}
+
+impl RewriteRule {
+    /// Applies the rule, returning `None` when it does not match `path`
+    pub fn apply(&self, path: &str) -> Option<String> {
+        match self {
+            RewriteRule::StripPrefix(prefix) => strip_segment_prefix(path, prefix)
+                .map(|rest| ensure_leading_slash(rest.to_string())),
+            RewriteRule::ReplacePrefix { from, to } => strip_segment_prefix(path, from)
+                .map(|rest| ensure_leading_slash(format!("{}{}", to.trim_end_matches('/'), rest))),
+            RewriteRule::Regex {
+                pattern,
+                replacement,
+            } => pattern.is_match(path).then(|| {
+                ensure_leading_slash(pattern.replace(path, replacement.as_str()).into_owned())
+            }),
+        }
+    }
+}
+
+impl PartialEq for RewriteRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for RewriteRule {}
+
+impl fmt::Display for RewriteRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RewriteRule::StripPrefix(prefix) => write!(f, "strip:{}", prefix),
+            RewriteRule::ReplacePrefix { from, to } => write!(f, "replace:{}=>{}", from, to),
+            RewriteRule::Regex {
+                pattern,
+                replacement,
+            } => write!(f, "regex:{}=>{}", pattern.as_str(), replacement),
+        }
+    }
+}
+
+/// Parses `strip:PREFIX`, `replace:FROM=>TO` or `regex:PATTERN=>REPLACEMENT`
+impl FromStr for RewriteRule {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (kind, rule) = spec.split_once(':').ok_or_else(|| {
+            format!(
+                "rewrite '{}' must start with strip:, replace: or regex:",
+                spec
+            )
+        })?;
+
+        match kind {
+            "strip" if !rule.is_empty() => Ok(RewriteRule::StripPrefix(rule.to_string())),
+            "replace" => {
+                let (from, to) = split_arrow(spec, rule)?;
+                Ok(RewriteRule::ReplacePrefix {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                })
+            }
+            "regex" => {
+                let (pattern, replacement) = split_arrow(spec, rule)?;
+                let pattern = Regex::new(pattern)
+                    .map_err(|e| format!("rewrite '{}' has an invalid regex: {}", spec, e))?;
+                Ok(RewriteRule::Regex {
+                    pattern,
+                    replacement: replacement.to_string(),
+                })
+            }
+            _ => Err(format!("unknown rewrite rule '{}'", spec)),
+        }
+    }
+}
+
+/// Splits `FROM=>TO`, reporting the whole spec on failure
+fn split_arrow<'a>(spec: &str, rule: &'a str) -> Result<(&'a str, &'a str), String> {
+    rule.split_once("=>")
+        .filter(|(from, _)| !from.is_empty())
+        .ok_or_else(|| format!("rewrite '{}' must look like FROM=>TO", spec))
+}
+
+/// Strips `prefix` from `path` on a whole-segment boundary
+fn strip_segment_prefix<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix)
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn ensure_leading_slash(path: String) -> String {
+    if path.starts_with('/') {
+        path
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Rewrites `path` with the first matching rule, or returns it unchanged
+pub fn rewrite_path(rules: &[RewriteRule], path: &str) -> String {
+    rules
+        .iter()
+        .find_map(|rule| rule.apply(path))
+        .unwrap_or_else(|| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(spec: &str) -> RewriteRule {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let strip = rule("strip:/api/v1");
+        assert_eq!(strip.apply("/api/v1/users").as_deref(), Some("/users"));
+        assert_eq!(strip.apply("/api/v1").as_deref(), Some("/"));
+        assert_eq!(strip.apply("/api/v10/users"), None);
+    }
+
+    #[test]
+    fn test_replace_prefix() {
+        let replace = rule("replace:/api/v1=>/v2");
+        assert_eq!(replace.apply("/api/v1/users").as_deref(), Some("/v2/users"));
+        assert_eq!(replace.apply("/api/v2/users"), None);
+
+        let to_root = rule("replace:/api/=>/");
+        assert_eq!(to_root.apply("/api/users").as_deref(), Some("/users"));
+    }
+
+    #[test]
+    fn test_regex_capture_rewrite() {
+        let regex = rule(r"regex:^/api/v(\d+)/(.*)$=>/$2/v$1");
+        assert_eq!(
+            regex.apply("/api/v3/users/7").as_deref(),
+            Some("/users/7/v3")
+        );
+        assert_eq!(regex.apply("/other"), None);
+    }
+
+    #[test]
+    fn test_rewrite_path_first_match_wins() {
+        let rules = vec![rule("strip:/api/v1"), rule("replace:/api=>/legacy")];
+        assert_eq!(rewrite_path(&rules, "/api/v1/users"), "/users");
+        assert_eq!(rewrite_path(&rules, "/api/v2/users"), "/legacy/v2/users");
+        assert_eq!(rewrite_path(&rules, "/health"), "/health");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("/api".parse::<RewriteRule>().is_err());
+        assert!("strip:".parse::<RewriteRule>().is_err());
+        assert!("replace:/api".parse::<RewriteRule>().is_err());
+        assert!("regex:([=>/".parse::<RewriteRule>().is_err());
+        assert!("rename:/a=>/b".parse::<RewriteRule>().is_err());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for spec in ["strip:/api", "replace:/a=>/b", "regex:^/x/(.*)$=>/$1"] {
+            assert_eq!(rule(spec).to_string(), spec);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Router construction from the application state.
+
+use axum::{
+    Router, middleware as axum_middleware,
+    routing::{any, get},
+};
+use std::sync::Arc;
+
+use crate::access_log::access_log;
+use crate::compression::{mark_pre_encoded, weaken_compressed_etag};
+use crate::handlers::{proxy_api, serve_static};
+use crate::live_reload::{LIVE_RELOAD_PATH, live_reload_events};
+use crate::metrics::{METRICS_PATH, metrics_endpoint, track_metrics};
+use crate::middleware::log_requests;
+use crate::state::AppState;
+use crate::trace_context::trace_requests;
+
+/// Builds the application router: one proxy route per configured prefix and
+/// the live reload and metrics endpoints, with everything else falling through
+/// to static file serving, all optionally compressed
+pub fn build_router(state: Arc<AppState>) -> Router {
+    let mut router = Router::new();
+    for route in &state.routes {
+        router = router.route(&format!("{}/{{*path}}", route.prefix), any(proxy_api));
+    }
+    if state.live_reload.is_some() {
+        router = router.route(LIVE_RELOAD_PATH, get(live_reload_events));
+    }
+    if state.metrics.is_some() {
+        router = router.route(METRICS_PATH, get(metrics_endpoint));
+    }
+
+    router = router.fallback(get(serve_static));
+    if let Some(compression) = &state.compression {
+        router = router
+            .layer(axum_middleware::map_response(mark_pre_encoded))
+            .layer(compression.layer())
+            .layer(axum_middleware::map_response(weaken_compressed_etag));
+    }
+
+    // Outside compression, so sent bytes are counted as they go on the wire
+    router
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            track_metrics,
+        ))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            access_log,
+        ))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            trace_requests,
+        ))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            log_requests,
+        ))
+        .with_state(state)
+}
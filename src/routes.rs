@@ -0,0 +1,267 @@
+//! Proxy route definitions mapping path prefixes to upstream backends.
+
+use axum::http::{HeaderName, HeaderValue};
+use std::{fmt, str::FromStr};
+
+use crate::rewrite::RewriteRule;
+use crate::upstream::{Timeouts, parse_timeout};
+
+/// A single proxy rule: requests under `prefix` are forwarded to `upstream`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyRoute {
+    /// Label shown in the request log (e.g. "AUTH")
+    pub label: String,
+    /// Path prefix matched against incoming requests (e.g. "/auth")
+    pub prefix: String,
+    /// Base URL of the backend (e.g. "http://localhost:9000")
+    pub upstream: String,
+    /// Whether `prefix` is removed before forwarding the path upstream
+    pub strip_prefix: bool,
+    /// Rewrite rules applied to the upstream path, first match wins
+    pub rewrites: Vec<RewriteRule>,
+    /// Extra headers set on every request forwarded upstream
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    /// Whether the client's `Host` is forwarded instead of the upstream's
+    pub preserve_host: bool,
+    /// `Host` sent upstream regardless of the client's (wins over `preserve_host`)
+    pub host_override: Option<HeaderValue>,
+    /// How long to wait on the backend before answering 504
+    pub timeouts: Timeouts,
+}
+
+impl ProxyRoute {
+    /// Creates a route, normalizing the prefix and upstream URL
+    pub fn new(label: impl Into<String>, prefix: &str, upstream: &str) -> Self {
+        ProxyRoute {
+            label: label.into(),
+            prefix: normalize_prefix(prefix),
+            upstream: normalize_upstream(upstream),
+            strip_prefix: false,
+            rewrites: Vec::new(),
+            headers: Vec::new(),
+            preserve_host: false,
+            host_override: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// The prefix that is re-appended to the upstream URL when forwarding
+    pub fn forwarded_prefix(&self) -> &str {
+        if self.strip_prefix { "" } else { &self.prefix }
+    }
+
+    /// The `Host` to send upstream, if not the upstream's own
+    ///
+    /// `client_host` is the host the client asked for.
+    pub fn upstream_host(&self, client_host: Option<&str>) -> Option<HeaderValue> {
+        self.host_override.clone().or_else(|| {
+            client_host
+                .filter(|_| self.preserve_host)
+                .and_then(|host| HeaderValue::from_str(host).ok())
+        })
+    }
+
+    /// Whether the request path falls under this route's prefix
+    ///
+    /// Matches only on whole path segments, so `/api` does not match `/apiary`.
+    pub fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+impl fmt::Display for ProxyRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/* → {}{}/",
+            self.prefix,
+            self.upstream,
+            self.forwarded_prefix()
+        )
+    }
+}
+
+/// Parses a route spec of the form `PREFIX=UPSTREAM[,OPTION]...`
+///
+/// Options are `strip`, `label=NAME`, `preserve-host`, `host-override=HOST`
+/// and the `connect-timeout=SECS`, `first-byte-timeout=SECS` and
+/// `timeout=SECS` timeouts, where `0` disables one.
+/// Example: `/auth=127.0.0.1:9000,strip,label=AUTH`. Without an explicit
+/// label, the prefix is upper-cased (`/auth` → `AUTH`).
+impl FromStr for ProxyRoute {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut parts = spec.split(',');
+        let target = parts.next().unwrap_or_default();
+
+        let (prefix, upstream) = target
+            .split_once('=')
+            .ok_or_else(|| format!("route '{}' must look like PREFIX=UPSTREAM", spec))?;
+        if upstream.is_empty() {
+            return Err(format!("route '{}' has an empty upstream", spec));
+        }
+
+        let prefix = normalize_prefix(prefix);
+        if prefix.is_empty() {
+            return Err(format!("route '{}' must have a non-root prefix", spec));
+        }
+
+        let mut route = ProxyRoute::new(default_label(&prefix), &prefix, upstream);
+        for option in parts {
+            match option.split_once('=') {
+                None if option == "strip" => route.strip_prefix = true,
+                Some(("label", label)) if !label.is_empty() => route.label = label.to_string(),
+                None if option == "preserve-host" => route.preserve_host = true,
+                Some(("host-override", host)) if !host.is_empty() => {
+                    route.host_override =
+                        Some(HeaderValue::from_str(host).map_err(|_| {
+                            format!("invalid host-override '{}' in '{}'", host, spec)
+                        })?)
+                }
+                Some(("connect-timeout", secs)) => route.timeouts.connect = parse_timeout(secs)?,
+                Some(("first-byte-timeout", secs)) => {
+                    route.timeouts.first_byte = parse_timeout(secs)?
+                }
+                Some(("timeout", secs)) => route.timeouts.total = parse_timeout(secs)?,
+                _ => return Err(format!("unknown route option '{}' in '{}'", option, spec)),
+            }
+        }
+
+        Ok(route)
+    }
+}
+
+/// Ensures a prefix has a leading slash and no trailing slash ("/" becomes "")
+pub fn normalize_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// Adds an `http://` scheme to bare `host:port` upstreams and drops trailing slashes
+pub fn normalize_upstream(upstream: &str) -> String {
+    let upstream = upstream.trim_end_matches('/');
+    if upstream.starts_with("http") {
+        upstream.to_string()
+    } else {
+        format!("http://{}", upstream)
+    }
+}
+
+/// Derives a log label from a prefix (e.g. "/files/v2" → "FILES/V2")
+pub fn default_label(prefix: &str) -> String {
+    prefix.trim_start_matches('/').to_uppercase()
+}
+
+/// Finds the route with the longest prefix matching the request path
+pub fn find_route<'a>(routes: &'a [ProxyRoute], path: &str) -> Option<&'a ProxyRoute> {
+    routes
+        .iter()
+        .filter(|route| route.matches(path))
+        .max_by_key(|route| route.prefix.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_route_minimal() {
+        let route: ProxyRoute = "/auth=127.0.0.1:9000".parse().unwrap();
+        assert_eq!(route.label, "AUTH");
+        assert_eq!(route.prefix, "/auth");
+        assert_eq!(route.upstream, "http://127.0.0.1:9000");
+        assert!(!route.strip_prefix);
+    }
+
+    #[test]
+    fn test_parse_route_with_options() {
+        let route: ProxyRoute = "files/=https://files.local/,strip,label=FS"
+            .parse()
+            .unwrap();
+        assert_eq!(route.label, "FS");
+        assert_eq!(route.prefix, "/files");
+        assert_eq!(route.upstream, "https://files.local");
+        assert!(route.strip_prefix);
+        assert_eq!(route.forwarded_prefix(), "");
+
+        let route: ProxyRoute = "/t=127.0.0.1:9000,preserve-host,host-override=tenant.test"
+            .parse()
+            .unwrap();
+        assert!(route.preserve_host);
+        assert_eq!(route.host_override.unwrap(), "tenant.test");
+
+        let route: ProxyRoute =
+            "/slow=127.0.0.1:9000,connect-timeout=2,first-byte-timeout=0,timeout=300"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            route.timeouts,
+            Timeouts {
+                connect: Some(Duration::from_secs(2)),
+                first_byte: None,
+                total: Some(Duration::from_secs(300)),
+            }
+        );
+    }
+
+    #[test]
+    fn test_upstream_host() {
+        let mut route = ProxyRoute::new("API", "/api", "127.0.0.1:8081");
+        assert_eq!(route.upstream_host(Some("app.test")), None);
+
+        route.preserve_host = true;
+        assert_eq!(route.upstream_host(Some("app.test")).unwrap(), "app.test");
+        assert_eq!(route.upstream_host(None), None);
+
+        route.host_override = Some(HeaderValue::from_static("tenant.test"));
+        assert_eq!(
+            route.upstream_host(Some("app.test")).unwrap(),
+            "tenant.test"
+        );
+    }
+
+    #[test]
+    fn test_parse_route_errors() {
+        assert!("/auth".parse::<ProxyRoute>().is_err());
+        assert!("/auth=".parse::<ProxyRoute>().is_err());
+        assert!("/=127.0.0.1:9000".parse::<ProxyRoute>().is_err());
+        assert!("/auth=127.0.0.1:9000,bogus".parse::<ProxyRoute>().is_err());
+        assert!(
+            "/auth=127.0.0.1:9000,host-override="
+                .parse::<ProxyRoute>()
+                .is_err()
+        );
+        assert!(
+            "/auth=127.0.0.1:9000,timeout=1m"
+                .parse::<ProxyRoute>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_route_matches_whole_segments() {
+        let route = ProxyRoute::new("API", "/api", "127.0.0.1:8081");
+        assert!(route.matches("/api"));
+        assert!(route.matches("/api/users"));
+        assert!(!route.matches("/apiary"));
+        assert!(!route.matches("/other/api"));
+    }
+
+    #[test]
+    fn test_find_route_prefers_longest_prefix() {
+        let routes = vec![
+            ProxyRoute::new("API", "/api", "127.0.0.1:8081"),
+            ProxyRoute::new("FILES", "/api/files", "127.0.0.1:8082"),
+        ];
+        assert_eq!(find_route(&routes, "/api/users").unwrap().label, "API");
+        assert_eq!(find_route(&routes, "/api/files/1").unwrap().label, "FILES");
+        assert!(find_route(&routes, "/static/app.js").is_none());
+    }
+}
@@ -0,0 +1,218 @@
+//! Graceful shutdown: stop accepting connections, then drain in-flight requests.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use hyper::body::{Frame, SizeHint};
+use std::{
+    pin::Pin,
+    process,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How long in-flight requests may take to finish once shutdown starts
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of requests whose response has not been fully sent yet, or whose
+/// upgraded connection is still open
+#[derive(Debug, Clone, Default)]
+pub struct InFlight {
+    inner: Arc<InFlightInner>,
+}
+
+#[derive(Debug, Default)]
+struct InFlightInner {
+    count: AtomicUsize,
+    /// Notified whenever the count drops to zero
+    idle: Notify,
+}
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.inner.count.load(Ordering::SeqCst)
+    }
+
+    /// Counts a request until the returned guard is dropped
+    pub(crate) fn enter(&self) -> InFlightGuard {
+        self.inner.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Resolves once nothing is in flight
+    pub async fn drained(&self) {
+        loop {
+            // Registered before the check, so a drop in between still wakes it
+            let idle = self.inner.idle.notified();
+            if self.count() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+pub(crate) struct InFlightGuard {
+    inner: Arc<InFlightInner>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.inner.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.inner.idle.notify_waiters();
+        }
+    }
+}
+
+/// Middleware counting each request as in flight until its body is sent
+///
+/// Responses are often streamed (large files, proxied bodies), so the count
+/// only drops once the body is done or the client goes away. The counter is
+/// also added to the request extensions, for upgraded connections that
+/// outlive their response to hold a guard of their own.
+pub async fn track_in_flight(
+    State(in_flight): State<InFlight>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let guard = in_flight.enter();
+    request.extensions_mut().insert(in_flight);
+    next.run(request).await.map(|body| {
+        Body::new(TrackedBody {
+            body,
+            _guard: guard,
+        })
+    })
+}
+
+/// A response body that holds an in-flight guard for as long as it lives
+struct TrackedBody {
+    body: Body,
+    _guard: InFlightGuard,
+}
+
+impl HttpBody for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// Resolves on the first SIGINT (Ctrl-C) or SIGTERM
+pub async fn signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl-C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// Shutdown trigger for `axum::serve(..).with_graceful_shutdown`
+///
+/// Resolves on the first signal and cancels `shutdown`; the process then
+/// exits anyway once `drain_timeout` elapses or a second signal arrives.
+pub async fn on_signal(in_flight: InFlight, shutdown: CancellationToken, drain_timeout: Duration) {
+    signal().await;
+    shutdown.cancel();
+    info!(
+        "Shutting down: draining {} in-flight request(s), up to {}s (Ctrl-C again to force)",
+        in_flight.count(),
+        drain_timeout.as_secs()
+    );
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(drain_timeout) => {
+                warn!(
+                    "Drain timeout elapsed with {} request(s) in flight, exiting",
+                    in_flight.count()
+                );
+                process::exit(1);
+            }
+            _ = signal() => {
+                warn!("Forced exit with {} request(s) in flight", in_flight.count());
+                process::exit(130);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tracked_body_counts_until_dropped() {
+        let in_flight = InFlight::default();
+        let body = TrackedBody {
+            body: Body::from("hello"),
+            _guard: in_flight.enter(),
+        };
+        assert_eq!(in_flight.count(), 1);
+        assert_eq!(body.size_hint().exact(), Some(5));
+
+        let bytes = axum::body::to_bytes(Body::new(body), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes, "hello");
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drained_waits_for_the_last_guard() {
+        let in_flight = InFlight::default();
+        let guard = in_flight.enter();
+        let drained = tokio::spawn({
+            let in_flight = in_flight.clone();
+            async move { in_flight.drained().await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!drained.is_finished());
+        drop(guard);
+        tokio::time::timeout(Duration::from_secs(1), drained)
+            .await
+            .expect("drained did not resolve")
+            .unwrap();
+    }
+}
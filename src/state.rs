@@ -1,16 +1,59 @@
 //! Shared application state.
 
 use std::path::PathBuf;
+use tokio_util::sync::CancellationToken;
+
+use crate::access_log::{AccessLogFile, LogFormat};
+use crate::compression::CompressionConfig;
+use crate::forwarded::ForwardedConfig;
+use crate::hop_by_hop::HopByHop;
+use crate::live_reload::LiveReload;
+use crate::metrics::Metrics;
+use crate::middleware::RequestIdHeader;
+use crate::otlp::Exporter;
+use crate::routes::{ProxyRoute, find_route};
+use crate::upstream::Clients;
 
 /// Shared application state accessible to all handlers
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct AppState {
-    /// Base URL of the backend API (e.g. "http://localhost:8081")
-    pub api_base_url: String,
-    /// Path prefix for API routes (e.g. "/pz")
-    pub api_path: String,
+    /// Proxy rules mapping path prefixes to backends
+    pub routes: Vec<ProxyRoute>,
     /// Root directory for static file serving
     pub static_dir: PathBuf,
-    /// Reusable HTTP client for proxying
-    pub client: reqwest::Client,
+    /// Document served for unmatched client-side routes when SPA mode is on
+    pub spa_fallback: Option<PathBuf>,
+    /// Response compression settings when compression is on
+    pub compression: Option<CompressionConfig>,
+    /// Reload notifications for browsers when live reload is on
+    pub live_reload: Option<LiveReload>,
+    /// Request counters served at the metrics endpoint when metrics are on
+    pub metrics: Option<Metrics>,
+    /// Connection-specific headers stripped from proxied messages
+    pub hop_by_hop: HopByHop,
+    /// Forwarding headers added to proxied requests
+    pub forwarded: ForwardedConfig,
+    /// Whether clients connect over TLS, as reported in `X-Forwarded-Proto`
+    pub https: bool,
+    /// Largest request body proxied upstream, in bytes; unlimited when unset
+    pub max_body_size: Option<u64>,
+    /// Access log format; anything but pretty writes a line per completed request
+    pub log_format: LogFormat,
+    /// Access log file written besides the console, when one is configured
+    pub access_log_file: Option<AccessLogFile>,
+    /// Span exporter when OpenTelemetry tracing is on
+    pub tracer: Option<Exporter>,
+    /// Header request IDs are read from, forwarded upstream in and returned in
+    pub request_id_header: RequestIdHeader,
+    /// Reusable HTTP clients for proxying
+    pub clients: Clients,
+    /// Cancelled when the server starts shutting down, ending long-lived streams
+    pub shutdown: CancellationToken,
+}
+
+impl AppState {
+    /// Finds the proxy route responsible for a request path
+    pub fn route_for(&self, path: &str) -> Option<&ProxyRoute> {
+        find_route(&self.routes, path)
+    }
 }
@@ -0,0 +1,417 @@
+//! TLS termination: certificate loading, local CA generation and the TLS listener.
+
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+    KeyUsagePurpose,
+};
+use rustls::{
+    ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs, io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use time::OffsetDateTime;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{TlsAcceptor, server::TlsStream};
+use tracing::{debug, error};
+
+/// How long a client may take to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshaked connections waiting to be picked up by the server
+const PENDING_CONNECTIONS: usize = 64;
+
+/// Leaf certificates are re-issued once they are older than this
+const LEAF_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Validity of a generated leaf certificate, kept under the 398 days browsers accept
+const LEAF_VALIDITY_DAYS: i64 = 397;
+
+/// A PEM certificate chain and its private key on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsFiles {
+    /// Certificate chain, leaf first
+    pub cert: PathBuf,
+    /// Private key matching the leaf certificate
+    pub key: PathBuf,
+}
+
+/// Directory where the local CA and generated leaf certificates are cached
+///
+/// Uses `$XDG_CACHE_HOME/local-rs/tls`, falling back to `~/.cache/local-rs/tls`.
+/// There is deliberately no fallback to the shared temp dir: anyone able to
+/// read the CA key there could mint certificates the browser trusts.
+pub fn default_cache_dir() -> Result<PathBuf, String> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|cache| cache.join("local-rs").join("tls"))
+        .ok_or_else(|| {
+            "no cache directory for the local CA: set XDG_CACHE_HOME or HOME".to_string()
+        })
+}
+
+/// Names a leaf certificate for `ip` must cover
+///
+/// Unspecified addresses (`0.0.0.0`, `::`) are reachable through loopback, so
+/// the loopback addresses are listed instead.
+pub fn subject_alt_names(ip: IpAddr) -> Vec<String> {
+    let mut names = vec!["localhost".to_string()];
+    if ip.is_unspecified() {
+        names.extend(["127.0.0.1".to_string(), "::1".to_string()]);
+    } else {
+        names.push(ip.to_string());
+    }
+    names
+}
+
+/// Returns a leaf certificate for `ip` signed by the local CA, generating both as needed
+///
+/// The CA (`ca.pem`) is created once and reused, so it only has to be trusted
+/// once; leaf certificates are cached per bind address and re-issued yearly,
+/// or as soon as the CA they were signed by has been replaced.
+pub fn self_signed(cache_dir: &Path, ip: IpAddr) -> Result<TlsFiles, String> {
+    create_private_dir(cache_dir)
+        .map_err(|e| format!("cannot create {}: {}", cache_dir.display(), e))?;
+
+    let stem = ip.to_string().replace(':', "_");
+    let files = TlsFiles {
+        cert: cache_dir.join(format!("{}.pem", stem)),
+        key: cache_dir.join(format!("{}-key.pem", stem)),
+    };
+    let (ca_pem, ca_key) = load_or_create_ca(cache_dir)?;
+    if is_fresh(&files.cert) && files.key.exists() && is_issued_by(&files.cert, &ca_pem) {
+        return Ok(files);
+    }
+
+    let issuer = Issuer::from_ca_cert_pem(&ca_pem, ca_key)
+        .map_err(|e| format!("cannot read local CA: {}", e))?;
+
+    let mut params = CertificateParams::new(subject_alt_names(ip))
+        .map_err(|e| format!("invalid certificate names: {}", e))?;
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "local-rs");
+    params.use_authority_key_identifier_extension = true;
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now - time::Duration::days(1);
+    params.not_after = now + time::Duration::days(LEAF_VALIDITY_DAYS);
+
+    let key = KeyPair::generate().map_err(|e| format!("cannot generate key: {}", e))?;
+    let cert = params
+        .signed_by(&key, &issuer)
+        .map_err(|e| format!("cannot sign certificate: {}", e))?;
+
+    write_file(&files.cert, &format!("{}{}", cert.pem(), ca_pem))?;
+    write_private_file(&files.key, &key.serialize_pem())?;
+    Ok(files)
+}
+
+/// Reads the cached local CA, creating it on first use
+fn load_or_create_ca(cache_dir: &Path) -> Result<(String, KeyPair), String> {
+    let cert_path = cache_dir.join("ca.pem");
+    let key_path = cache_dir.join("ca-key.pem");
+
+    if let (Ok(cert), Ok(key)) = (
+        fs::read_to_string(&cert_path),
+        fs::read_to_string(&key_path),
+    ) {
+        let key = KeyPair::from_pem(&key)
+            .map_err(|e| format!("invalid CA key {}: {}", key_path.display(), e))?;
+        return Ok((cert, key));
+    }
+
+    let mut params = CertificateParams::default();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "local-rs development CA");
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+
+    let key = KeyPair::generate().map_err(|e| format!("cannot generate CA key: {}", e))?;
+    let cert = params
+        .self_signed(&key)
+        .map_err(|e| format!("cannot create CA: {}", e))?;
+
+    write_file(&cert_path, &cert.pem())?;
+    write_private_file(&key_path, &key.serialize_pem())?;
+    Ok((cert.pem(), key))
+}
+
+/// Whether a cached certificate exists and is young enough to reuse
+fn is_fresh(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < LEAF_MAX_AGE)
+}
+
+/// Whether the cached chain at `path` was issued by the CA in `ca_pem`
+///
+/// Leaves are cached with their CA appended, so a chain ending in another CA
+/// was signed by one that has since been regenerated.
+fn is_issued_by(path: &Path, ca_pem: &str) -> bool {
+    let Ok(ca) = CertificateDer::from_pem_slice(ca_pem.as_bytes()) else {
+        return false;
+    };
+    load_certs(path).is_ok_and(|certs| certs.len() > 1 && certs.last() == Some(&ca))
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    fs::write(path, contents).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+/// Writes a private key readable by the owner only
+fn write_private_file(path: &Path, contents: &str) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(path)
+        .and_then(|mut file| {
+            // The mode only applies to new files; tighten a key being replaced too
+            #[cfg(unix)]
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+            io::Write::write_all(&mut file, contents.as_bytes())
+        })
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+/// Creates the cache dir (and missing parents) accessible to the owner only
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        // An existing dir keeps its mode, so tighten it as well
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+    }
+    #[cfg(not(unix))]
+    fs::create_dir_all(dir)
+}
+
+/// Reads a PEM certificate chain, leaf first
+pub fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("cannot read certificates from {}: {}", path.display(), e))?;
+
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+/// Builds the rustls server configuration for a certificate chain and key file
+pub fn server_config(
+    certs: Vec<CertificateDer<'static>>,
+    key_path: &Path,
+) -> Result<ServerConfig, String> {
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| format!("cannot read private key {}: {}", key_path.display(), e))?;
+
+    let mut config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| format!("invalid TLS configuration: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// SHA-256 fingerprint of a certificate, as colon-separated hex
+pub fn fingerprint(cert: &CertificateDer<'_>) -> String {
+    Sha256::digest(cert.as_ref())
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Listener that terminates TLS before handing connections to `axum::serve`
+///
+/// Handshakes run in their own tasks, so a slow or stalled client cannot hold
+/// up the connections accepted after it.
+pub struct TlsListener {
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    /// Starts accepting TLS connections on `listener`
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, incoming) = mpsc::channel(PENDING_CONNECTIONS);
+        tokio::spawn(accept_loop(listener, TlsAcceptor::from(config), sender));
+
+        Ok(TlsListener {
+            incoming,
+            local_addr,
+        })
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(connection) => connection,
+            // The accept loop only stops once this listener is gone
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Accepts TCP connections and forwards them once their TLS handshake succeeds
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    sender: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    while !sender.is_closed() {
+        let (stream, addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("Accept failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = sender.send((stream, addr)).await;
+                }
+                Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", addr, e),
+                Err(_) => debug!("TLS handshake with {} timed out", addr),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_alt_names() {
+        assert_eq!(
+            subject_alt_names("127.0.0.1".parse().unwrap()),
+            ["localhost", "127.0.0.1"]
+        );
+        assert_eq!(
+            subject_alt_names("0.0.0.0".parse().unwrap()),
+            ["localhost", "127.0.0.1", "::1"]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_format() {
+        let fingerprint = fingerprint(&CertificateDer::from(b"local-rs".to_vec()));
+        assert_eq!(fingerprint.len(), 32 * 3 - 1);
+        assert!(fingerprint.split(':').all(|byte| byte.len() == 2));
+    }
+
+    #[test]
+    fn test_self_signed_is_cached_and_loadable() {
+        let cache_dir = std::env::current_dir()
+            .unwrap()
+            .join("target")
+            .join("test_tls_cache");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let ip = "127.0.0.1".parse().unwrap();
+
+        let files = self_signed(&cache_dir, ip).unwrap();
+        let certs = load_certs(&files.cert).unwrap();
+        assert_eq!(certs.len(), 2, "leaf should be followed by the CA");
+        assert!(server_config(certs.clone(), &files.key).is_ok());
+
+        // A second run reuses the cached leaf instead of issuing a new one
+        let again = self_signed(&cache_dir, ip).unwrap();
+        assert_eq!(again, files);
+        assert_eq!(
+            fingerprint(&load_certs(&again.cert).unwrap()[0]),
+            fingerprint(&certs[0])
+        );
+
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn test_leaf_is_reissued_for_a_new_ca() {
+        let cache_dir = std::env::current_dir()
+            .unwrap()
+            .join("target")
+            .join("test_tls_new_ca");
+        let _ = fs::remove_dir_all(&cache_dir);
+        let ip = "127.0.0.1".parse().unwrap();
+
+        let files = self_signed(&cache_dir, ip).unwrap();
+        let old_leaf = fingerprint(&load_certs(&files.cert).unwrap()[0]);
+
+        // The CA is regenerated, e.g. after being deleted to revoke trust
+        fs::remove_file(cache_dir.join("ca.pem")).unwrap();
+        fs::remove_file(cache_dir.join("ca-key.pem")).unwrap();
+        let files = self_signed(&cache_dir, ip).unwrap();
+        let certs = load_certs(&files.cert).unwrap();
+        assert_ne!(fingerprint(&certs[0]), old_leaf);
+        assert_eq!(
+            certs[1],
+            load_certs(&cache_dir.join("ca.pem")).unwrap()[0],
+            "leaf should be chained to the new CA"
+        );
+
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_private_keys_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let cache_dir = std::env::current_dir()
+            .unwrap()
+            .join("target")
+            .join("test_tls_permissions");
+        let _ = fs::remove_dir_all(&cache_dir);
+        // A pre-existing, world-readable dir is tightened too
+        fs::create_dir_all(&cache_dir).unwrap();
+        fs::set_permissions(&cache_dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let files = self_signed(&cache_dir, "127.0.0.1".parse().unwrap()).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&cache_dir), 0o700);
+        assert_eq!(mode(&cache_dir.join("ca-key.pem")), 0o600);
+        assert_eq!(mode(&files.key), 0o600);
+
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+}
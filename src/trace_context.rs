@@ -0,0 +1,297 @@
+//! W3C Trace Context propagation, with a server span per request and a
+//! client span per upstream call, exported over OTLP.
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::{fmt, str::FromStr, sync::Arc, time::SystemTime};
+
+use crate::forwarded::ClientAddr;
+use crate::otlp::{AttributeValue, Exporter, Span, SpanKind};
+use crate::state::AppState;
+
+pub const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+pub const TRACESTATE: HeaderName = HeaderName::from_static("tracestate");
+
+/// A `traceparent` header: `00-<trace id>-<parent span id>-<flags>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub sampled: bool,
+}
+
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.sampled as u8
+        )
+    }
+}
+
+/// Parses a `traceparent` value
+///
+/// Later versions may append fields, so only the first four are read from
+/// them; version `ff`, all-zero IDs and uppercase hex are invalid.
+impl FromStr for TraceParent {
+    type Err = String;
+
+    fn from_str(header: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid traceparent '{}'", header);
+        let hex = |field: &str, len: usize| {
+            (field.len() == len
+                && field
+                    .bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)))
+            .then(|| u128::from_str_radix(field, 16).ok())
+            .flatten()
+        };
+
+        let mut fields = header.trim().split('-');
+        let (Some(version), Some(trace_id), Some(span_id), Some(flags)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+        let version = hex(version, 2).ok_or_else(invalid)?;
+        if version == 0xff || (version == 0 && fields.next().is_some()) {
+            return Err(invalid());
+        }
+        let trace_id = hex(trace_id, 32).filter(|&id| id != 0);
+        let span_id = hex(span_id, 16).filter(|&id| id != 0);
+        let flags = hex(flags, 2);
+        match (trace_id, span_id, flags) {
+            (Some(trace_id), Some(span_id), Some(flags)) => Ok(TraceParent {
+                trace_id,
+                span_id: span_id as u64,
+                sampled: flags & 1 == 1,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The trace a request belongs to, stored in its extensions for the spans it starts
+#[derive(Debug, Clone)]
+pub struct RequestTrace {
+    pub trace_id: u128,
+    /// ID of the request's server span
+    pub span_id: u64,
+    /// Whether spans of this trace are exported
+    pub sampled: bool,
+    /// Vendor state passed through from the client unchanged
+    pub tracestate: Option<HeaderValue>,
+    exporter: Exporter,
+}
+
+impl RequestTrace {
+    /// Starts a client span for an upstream call and adds its `traceparent`
+    /// (and the client's `tracestate`) to the upstream request headers
+    pub fn client_span(&self, method: &Method, url: &str, headers: &mut HeaderMap) -> ClientSpan {
+        let parent = TraceParent {
+            trace_id: self.trace_id,
+            span_id: new_span_id(),
+            sampled: self.sampled,
+        };
+        if let Ok(value) = HeaderValue::from_str(&parent.to_string()) {
+            headers.insert(TRACEPARENT, value);
+        }
+        headers.remove(TRACESTATE);
+        if let Some(tracestate) = &self.tracestate {
+            headers.insert(TRACESTATE, tracestate.clone());
+        }
+
+        let mut attributes = vec![
+            ("http.request.method", method.as_str().into()),
+            ("url.full", url.into()),
+        ];
+        if let Ok(url) = reqwest::Url::parse(url) {
+            if let Some(host) = url.host_str() {
+                attributes.push(("server.address", host.into()));
+            }
+            if let Some(port) = url.port_or_known_default() {
+                attributes.push(("server.port", port.into()));
+            }
+        }
+        ClientSpan {
+            trace: self.clone(),
+            span_id: parent.span_id,
+            name: method.to_string(),
+            start: SystemTime::now(),
+            attributes,
+        }
+    }
+}
+
+/// A span for an upstream call, exported when ended
+#[derive(Debug)]
+pub struct ClientSpan {
+    trace: RequestTrace,
+    span_id: u64,
+    name: String,
+    start: SystemTime,
+    attributes: Vec<(&'static str, AttributeValue)>,
+}
+
+impl ClientSpan {
+    /// Ends the span with the upstream's response status, or the error class
+    /// of a failed call
+    pub fn end(mut self, outcome: Result<StatusCode, &str>) {
+        let error = match outcome {
+            Ok(status) => {
+                self.attributes
+                    .push(("http.response.status_code", status.as_u16().into()));
+                (status.is_client_error() || status.is_server_error())
+                    .then(|| status.as_u16().to_string())
+            }
+            Err(kind) => Some(kind.to_string()),
+        };
+        if let Some(error) = &error {
+            self.attributes.push(("error.type", error.clone().into()));
+        }
+        if !self.trace.sampled {
+            return;
+        }
+        self.trace.exporter.export(Span {
+            trace_id: self.trace.trace_id,
+            span_id: self.span_id,
+            parent_span_id: Some(self.trace.span_id),
+            name: self.name,
+            kind: SpanKind::Client,
+            start: self.start,
+            end: SystemTime::now(),
+            attributes: self.attributes,
+            error,
+        });
+    }
+}
+
+/// A random non-zero trace ID
+pub fn new_trace_id() -> u128 {
+    rand::random::<u128>().max(1)
+}
+
+/// A random non-zero span ID
+pub fn new_span_id() -> u64 {
+    rand::random::<u64>().max(1)
+}
+
+/// Middleware recording a server span per request when tracing is on
+///
+/// The span continues the client's trace when it sent a valid `traceparent`
+/// and starts a new, sampled one otherwise. It ends when the response
+/// headers are ready.
+pub async fn trace_requests(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(exporter) = state.tracer.clone() else {
+        return next.run(request).await;
+    };
+
+    let headers = request.headers();
+    let parent = headers
+        .get(TRACEPARENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<TraceParent>().ok());
+    let trace = RequestTrace {
+        trace_id: parent.map_or_else(new_trace_id, |parent| parent.trace_id),
+        span_id: new_span_id(),
+        sampled: parent.is_none_or(|parent| parent.sampled),
+        tracestate: parent.and(headers.get(TRACESTATE).cloned()),
+        exporter: exporter.clone(),
+    };
+
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let route = state
+        .route_for(uri.path())
+        .map(|route| format!("{}/*", route.prefix));
+    let mut attributes: Vec<(&'static str, AttributeValue)> = vec![
+        ("http.request.method", method.as_str().into()),
+        ("url.path", uri.path().into()),
+    ];
+    if let Some(query) = uri.query() {
+        attributes.push(("url.query", query.into()));
+    }
+    if let Some(route) = &route {
+        attributes.push(("http.route", route.as_str().into()));
+    }
+    if let Some(ConnectInfo(ClientAddr(addr))) = request.extensions().get() {
+        attributes.push(("client.address", addr.ip().to_string().into()));
+    }
+    if let Some(id) = request.extensions().get::<String>() {
+        attributes.push(("local_rs.request_id", id.as_str().into()));
+    }
+
+    request.extensions_mut().insert(trace.clone());
+    let start = SystemTime::now();
+    let response = next.run(request).await;
+
+    if trace.sampled {
+        let status = response.status();
+        attributes.push(("http.response.status_code", status.as_u16().into()));
+        exporter.export(Span {
+            trace_id: trace.trace_id,
+            span_id: trace.span_id,
+            parent_span_id: parent.map(|parent| parent.span_id),
+            name: match route {
+                Some(route) => format!("{} {}", method, route),
+                None => method.to_string(),
+            },
+            kind: SpanKind::Server,
+            start,
+            end: SystemTime::now(),
+            attributes,
+            error: status
+                .is_server_error()
+                .then(|| status.as_u16().to_string()),
+        });
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let parent: TraceParent = EXAMPLE.parse().unwrap();
+        assert_eq!(parent.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(parent.span_id, 0x00f067aa0ba902b7);
+        assert!(parent.sampled);
+        assert_eq!(parent.to_string(), EXAMPLE);
+
+        let unsampled: TraceParent = EXAMPLE.replace("-01", "-00").parse().unwrap();
+        assert!(!unsampled.sampled);
+
+        // Future versions may carry more fields
+        let future: TraceParent = format!("cc{}-extra", &EXAMPLE[2..]).parse().unwrap();
+        assert_eq!(future.span_id, parent.span_id);
+    }
+
+    #[test]
+    fn test_rejects_invalid_traceparent() {
+        for invalid in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert!(invalid.parse::<TraceParent>().is_err(), "{:?}", invalid);
+        }
+    }
+}
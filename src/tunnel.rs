@@ -0,0 +1,367 @@
+//! HTTP Upgrade (WebSocket) tunneling between the client and the backend.
+
+use axum::{
+    extract::OptionalFromRequestParts,
+    http::{HeaderMap, HeaderValue, header, request::Parts},
+};
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use owo_colors::OwoColorize;
+use std::{convert::Infallible, time::Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::colors::colored_id;
+use crate::shutdown::InFlightGuard;
+
+/// Size of the buffer used when pumping bytes through the tunnel
+const TUNNEL_BUFFER_SIZE: usize = 16 * 1024;
+
+/// WebSocket close status sent to both sides when local-rs shuts down
+const GOING_AWAY: u16 = 1001;
+
+/// Checks whether a request asks to switch protocols
+///
+/// Requires both an `Upgrade` header and a `Connection` header listing the
+/// `upgrade` token (case-insensitive, possibly among other tokens).
+pub fn is_upgrade_request(headers: &HeaderMap) -> bool {
+    let connection_upgrade = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+
+    connection_upgrade && headers.contains_key(header::UPGRADE)
+}
+
+/// Pending upgrade of the client connection, extracted from `Upgrade` requests
+///
+/// Extracting it takes the connection upgrade out of the request, so it can
+/// be awaited once the backend has agreed to switch protocols.
+pub struct ClientUpgrade {
+    on_upgrade: OnUpgrade,
+    protocol: HeaderValue,
+}
+
+impl ClientUpgrade {
+    /// Re-adds the `Connection`/`Upgrade` pair that hop-by-hop filtering removed
+    pub fn restore_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(header::UPGRADE, self.protocol.clone());
+    }
+
+    /// Whether the requested protocol is WebSocket, whose frames we can count
+    fn is_websocket(&self) -> bool {
+        self.protocol
+            .to_str()
+            .is_ok_and(|protocol| protocol.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for ClientUpgrade
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Option<Self>, Infallible> {
+        if !is_upgrade_request(&parts.headers) {
+            return Ok(None);
+        }
+
+        let Some(protocol) = parts.headers.get(header::UPGRADE).cloned() else {
+            return Ok(None);
+        };
+
+        Ok(parts
+            .extensions
+            .remove::<OnUpgrade>()
+            .map(|on_upgrade| ClientUpgrade {
+                on_upgrade,
+                protocol,
+            }))
+    }
+}
+
+/// Incrementally counts WebSocket frames in a byte stream
+///
+/// Only frame headers are parsed; payloads are skipped without being buffered,
+/// so the counter works on arbitrary chunk boundaries.
+#[derive(Debug, Default)]
+pub struct FrameCounter {
+    frames: u64,
+    header: Vec<u8>,
+    remaining_payload: u64,
+}
+
+impl FrameCounter {
+    /// Feeds the next chunk of the stream into the counter
+    pub fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining_payload > 0 {
+                let skip = self.remaining_payload.min(data.len() as u64);
+                self.remaining_payload -= skip;
+                data = &data[skip as usize..];
+                continue;
+            }
+
+            self.header.push(data[0]);
+            data = &data[1..];
+
+            if let Some(payload_len) = parse_frame_header(&self.header) {
+                self.frames += 1;
+                self.remaining_payload = payload_len;
+                self.header.clear();
+            }
+        }
+    }
+
+    /// Number of complete frame headers seen so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Whether the stream is between two frames, where another may be sent
+    pub fn at_frame_boundary(&self) -> bool {
+        self.header.is_empty() && self.remaining_payload == 0
+    }
+}
+
+/// A WebSocket close frame carrying `status`
+///
+/// Frames sent to the backend stand in for the client's, so they are masked.
+fn close_frame(status: u16, masked: bool) -> Vec<u8> {
+    let payload = status.to_be_bytes();
+    if !masked {
+        return vec![0x88, 0x02, payload[0], payload[1]];
+    }
+    let mask: [u8; 4] = rand::random();
+    let mut frame = vec![0x88, 0x82];
+    frame.extend(mask);
+    frame.extend(payload.iter().zip(mask).map(|(byte, mask)| byte ^ mask));
+    frame
+}
+
+/// Returns the payload length once `header` holds a complete frame header
+fn parse_frame_header(header: &[u8]) -> Option<u64> {
+    if header.len() < 2 {
+        return None;
+    }
+
+    let masked = header[1] & 0x80 != 0;
+    let (extended_len, payload_len) = match header[1] & 0x7f {
+        126 => (2, None),
+        127 => (8, None),
+        len => (0, Some(u64::from(len))),
+    };
+    let header_len = 2 + extended_len + if masked { 4 } else { 0 };
+
+    if header.len() < header_len {
+        return None;
+    }
+
+    payload_len.or_else(|| {
+        Some(
+            header[2..2 + extended_len]
+                .iter()
+                .fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte)),
+        )
+    })
+}
+
+/// Traffic statistics for one direction of a tunnel
+#[derive(Debug, Default)]
+struct TunnelStats {
+    bytes: u64,
+    frames: Option<u64>,
+}
+
+/// Copies bytes from `reader` to `writer` until either side closes or
+/// `shutdown` is cancelled
+///
+/// On shutdown, WebSocket peers get a "going away" close frame, unless a
+/// frame is only half written, in which case the connection just ends.
+/// `masked` tells whether `writer` leads to the backend.
+async fn pump<R, W>(
+    mut reader: R,
+    mut writer: W,
+    mut counter: Option<FrameCounter>,
+    masked: bool,
+    shutdown: CancellationToken,
+) -> TunnelStats
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; TUNNEL_BUFFER_SIZE];
+    let mut stats = TunnelStats::default();
+
+    loop {
+        let read = tokio::select! {
+            read = reader.read(&mut buf) => read,
+            _ = shutdown.cancelled() => {
+                if counter.as_ref().is_some_and(FrameCounter::at_frame_boundary) {
+                    let _ = writer.write_all(&close_frame(GOING_AWAY, masked)).await;
+                }
+                break;
+            }
+        };
+        let n = match read {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+
+        if let Some(counter) = counter.as_mut() {
+            counter.feed(&buf[..n]);
+        }
+        stats.bytes += n as u64;
+
+        if writer.write_all(&buf[..n]).await.is_err() || writer.flush().await.is_err() {
+            break;
+        }
+    }
+
+    let _ = writer.shutdown().await;
+    stats.frames = counter.map(|counter| counter.frames());
+    stats
+}
+
+/// Formats one direction of the tunnel statistics for the close log line
+fn describe(stats: &TunnelStats) -> String {
+    match stats.frames {
+        Some(frames) => format!("{} frames/{}B", frames, stats.bytes),
+        None => format!("{}B", stats.bytes),
+    }
+}
+
+/// Spawns the task that bridges the upgraded client and backend connections
+///
+/// Must be called after the backend answered `101 Switching Protocols`; the
+/// client side upgrades once that response has been sent back to it. The
+/// tunnel counts as in flight through `guard` until it closes, and is closed
+/// when `shutdown` is cancelled.
+pub(crate) fn spawn_tunnel(
+    id: String,
+    client: ClientUpgrade,
+    backend: reqwest::Response,
+    guard: Option<InFlightGuard>,
+    shutdown: CancellationToken,
+) {
+    tokio::spawn(async move {
+        let _guard = guard;
+        let count_frames = client.is_websocket();
+
+        let backend = match backend.upgrade().await {
+            Ok(upgraded) => upgraded,
+            Err(e) => {
+                error!("{} Backend upgrade failed: {}", colored_id(&id), e);
+                return;
+            }
+        };
+        let client = match client.on_upgrade.await {
+            Ok(upgraded) => TokioIo::new(upgraded),
+            Err(e) => {
+                error!("{} Client upgrade failed: {}", colored_id(&id), e);
+                return;
+            }
+        };
+
+        info!("{} ⇄ {} open", colored_id(&id), "WS".cyan());
+        let opened_at = Instant::now();
+
+        let (client_read, client_write) = tokio::io::split(client);
+        let (backend_read, backend_write) = tokio::io::split(backend);
+        let new_counter = || count_frames.then(FrameCounter::default);
+
+        let (upstream, downstream) = tokio::join!(
+            pump(
+                client_read,
+                backend_write,
+                new_counter(),
+                true,
+                shutdown.clone()
+            ),
+            pump(backend_read, client_write, new_counter(), false, shutdown),
+        );
+
+        info!(
+            "{} ⇄ {} closed (↑ {}, ↓ {}, {}ms)",
+            colored_id(&id),
+            "WS".cyan(),
+            describe(&upstream),
+            describe(&downstream),
+            opened_at.elapsed().as_millis()
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_upgrade_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+        assert!(!is_upgrade_request(&headers));
+
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("keep-alive, Upgrade"),
+        );
+        assert!(is_upgrade_request(&headers));
+
+        headers.remove(header::UPGRADE);
+        assert!(!is_upgrade_request(&headers));
+    }
+
+    #[test]
+    fn test_frame_counter_small_frames() {
+        let mut counter = FrameCounter::default();
+        // Unmasked text frame "hi" followed by an empty masked ping
+        counter.feed(&[0x81, 0x02, b'h', b'i', 0x89, 0x80, 1, 2, 3, 4]);
+        assert_eq!(counter.frames(), 2);
+    }
+
+    #[test]
+    fn test_frame_counter_extended_length_across_chunks() {
+        let mut frame = vec![0x82, 126, 0x01, 0x00];
+        frame.extend(std::iter::repeat_n(0u8, 256));
+
+        let mut counter = FrameCounter::default();
+        for chunk in frame.chunks(3) {
+            counter.feed(chunk);
+        }
+        assert_eq!(counter.frames(), 1);
+
+        // The next frame header must be parsed after the skipped payload
+        counter.feed(&[0x81, 0x00]);
+        assert_eq!(counter.frames(), 2);
+    }
+
+    #[test]
+    fn test_close_frame_masking() {
+        assert_eq!(close_frame(GOING_AWAY, false), [0x88, 0x02, 0x03, 0xe9]);
+
+        let masked = close_frame(GOING_AWAY, true);
+        assert_eq!(&masked[..2], [0x88, 0x82]);
+        assert_eq!(masked[6] ^ masked[2], 0x03);
+        assert_eq!(masked[7] ^ masked[3], 0xe9);
+
+        let mut counter = FrameCounter::default();
+        counter.feed(&masked);
+        assert_eq!(counter.frames(), 1);
+        assert!(counter.at_frame_boundary());
+        counter.feed(&[0x81, 0x05, b'h']);
+        assert!(!counter.at_frame_boundary());
+    }
+
+    #[test]
+    fn test_parse_frame_header_64bit_length() {
+        let header = [0x82, 127, 0, 0, 0, 0, 0, 1, 0, 0];
+        assert_eq!(parse_frame_header(&header), Some(65536));
+        assert_eq!(parse_frame_header(&header[..9]), None);
+    }
+}
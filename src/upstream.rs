@@ -0,0 +1,320 @@
+//! HTTP clients for upstream requests, their timeouts, and how failures map to statuses.
+
+use axum::http::StatusCode;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, io, iter,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Time allowed to establish a connection unless the route sets its own
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time allowed until the response headers arrive unless the route sets its own
+pub const DEFAULT_FIRST_BYTE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a route waits on its backend; `None` waits forever
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Establishing the TCP (and TLS) connection
+    pub connect: Option<Duration>,
+    /// From sending the request until the response headers arrive
+    pub first_byte: Option<Duration>,
+    /// The whole exchange, including streaming the response body
+    pub total: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            connect: Some(DEFAULT_CONNECT_TIMEOUT),
+            first_byte: Some(DEFAULT_FIRST_BYTE_TIMEOUT),
+            total: None,
+        }
+    }
+}
+
+/// A timeout in whole seconds, where `0` means none
+pub fn timeout_from_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Parses a timeout in whole seconds, where `0` means none
+pub fn parse_timeout(secs: &str) -> Result<Option<Duration>, String> {
+    secs.parse()
+        .map(timeout_from_secs)
+        .map_err(|_| format!("invalid timeout '{}' (expected seconds)", secs))
+}
+
+/// Reusable HTTP clients for proxying, one per connect timeout
+///
+/// reqwest sets the connect timeout per client, so routes that share one
+/// share a client and its connection pool.
+#[derive(Debug, Clone, Default)]
+pub struct Clients {
+    clients: Arc<Mutex<HashMap<Option<Duration>, reqwest::Client>>>,
+}
+
+impl Clients {
+    pub fn get(&self, connect_timeout: Option<Duration>) -> reqwest::Client {
+        let mut clients = self.clients.lock().unwrap();
+        clients
+            .entry(connect_timeout)
+            .or_insert_with(|| {
+                let mut builder = reqwest::Client::builder();
+                if let Some(timeout) = connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                builder.build().expect("Failed to build HTTP client")
+            })
+            .clone()
+    }
+}
+
+/// Why an upstream request failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The connection was not established within the connect timeout
+    ConnectTimeout,
+    /// The response headers did not arrive within the first-byte timeout
+    FirstByteTimeout,
+    /// The exchange did not finish within the total timeout
+    Timeout,
+    /// Nothing listens on the upstream's port
+    Refused,
+    /// The upstream's host name did not resolve
+    Dns,
+    /// The TLS handshake with an `https` upstream failed
+    Tls,
+    /// The connection could not be established for another reason
+    Connect,
+    /// The request failed after connecting
+    Request,
+}
+
+impl ErrorKind {
+    /// Tells failures apart by walking the error's source chain
+    ///
+    /// The HTTP stack reports DNS and TLS failures only in its messages, so
+    /// those are matched on text.
+    pub fn classify(error: &reqwest::Error) -> Self {
+        match (error.is_timeout(), error.is_connect()) {
+            (true, true) => return ErrorKind::ConnectTimeout,
+            (true, false) => return ErrorKind::Timeout,
+            (false, false) => return ErrorKind::Request,
+            (false, true) => {}
+        }
+
+        let causes = || iter::successors(Some(error as &dyn Error), |&cause| cause.source());
+        let refused = causes().any(|cause| {
+            cause
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::ConnectionRefused)
+        });
+        let text = causes()
+            .map(|cause| cause.to_string().to_lowercase())
+            .collect::<Vec<_>>()
+            .join(": ");
+        if refused {
+            ErrorKind::Refused
+        } else if text.contains("dns error") {
+            ErrorKind::Dns
+        } else if ["tls", "ssl", "certificate"]
+            .iter()
+            .any(|word| text.contains(word))
+        {
+            ErrorKind::Tls
+        } else {
+            ErrorKind::Connect
+        }
+    }
+
+    /// A suggestion for fixing the failure on `upstream`, if there is one
+    pub fn hint(self, upstream: &str) -> Option<String> {
+        let url = reqwest::Url::parse(upstream).ok()?;
+        let host = url.host_str()?;
+        let port = url.port_or_known_default()?;
+        Some(match self {
+            ErrorKind::Refused | ErrorKind::Connect => {
+                format!("Is your backend running on {}:{}?", host, port)
+            }
+            ErrorKind::ConnectTimeout => format!(
+                "Nothing answered on {}:{}; is the address right and reachable?",
+                host, port
+            ),
+            ErrorKind::Dns => format!(
+                "Could not resolve '{}'; check the host in the route's upstream",
+                host
+            ),
+            ErrorKind::Tls => format!(
+                "Does {}:{} serve HTTPS with a certificate this machine trusts?",
+                host, port
+            ),
+            ErrorKind::FirstByteTimeout => "The backend took the request but did not answer in \
+                time; raise the route's first-byte-timeout for slow endpoints"
+                .to_string(),
+            ErrorKind::Timeout => "The backend did not finish in time; raise the route's \
+                timeout for long-running requests"
+                .to_string(),
+            ErrorKind::Request => return None,
+        })
+    }
+
+    /// Short identifier used as a metrics label
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::ConnectTimeout => "connect_timeout",
+            ErrorKind::FirstByteTimeout => "first_byte_timeout",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Refused => "refused",
+            ErrorKind::Dns => "dns",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Request => "request",
+        }
+    }
+
+    /// 504 Gateway Timeout for timeouts, 502 Bad Gateway for everything else
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorKind::ConnectTimeout | ErrorKind::FirstByteTimeout | ErrorKind::Timeout => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            ErrorKind::Refused
+            | ErrorKind::Dns
+            | ErrorKind::Tls
+            | ErrorKind::Connect
+            | ErrorKind::Request => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::ConnectTimeout => "connect timeout",
+            ErrorKind::FirstByteTimeout => "first-byte timeout",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Refused => "connection refused",
+            ErrorKind::Dns => "DNS error",
+            ErrorKind::Tls => "TLS error",
+            ErrorKind::Connect => "connection error",
+            ErrorKind::Request => "request error",
+        })
+    }
+}
+
+/// A failed upstream request
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)
+    }
+}
+
+impl From<reqwest::Error> for UpstreamError {
+    fn from(error: reqwest::Error) -> Self {
+        UpstreamError {
+            kind: ErrorKind::classify(&error),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Sends `request`, giving up if the response headers take longer than `first_byte`
+pub async fn send(
+    request: reqwest::RequestBuilder,
+    first_byte: Option<Duration>,
+) -> Result<reqwest::Response, UpstreamError> {
+    let Some(first_byte) = first_byte else {
+        return Ok(request.send().await?);
+    };
+    match tokio::time::timeout(first_byte, request.send()).await {
+        Ok(response) => Ok(response?),
+        Err(_) => Err(UpstreamError {
+            kind: ErrorKind::FirstByteTimeout,
+            message: format!("no response within {}s", first_byte.as_secs_f64()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("30").unwrap(), Some(Duration::from_secs(30)));
+        assert_eq!(parse_timeout("0").unwrap(), None);
+        assert!(parse_timeout("5s").is_err());
+        assert!(parse_timeout("-1").is_err());
+    }
+
+    #[test]
+    fn test_timeouts_map_to_gateway_timeout() {
+        for kind in [
+            ErrorKind::ConnectTimeout,
+            ErrorKind::FirstByteTimeout,
+            ErrorKind::Timeout,
+        ] {
+            assert_eq!(kind.status(), StatusCode::GATEWAY_TIMEOUT, "{}", kind);
+        }
+        for kind in [
+            ErrorKind::Refused,
+            ErrorKind::Dns,
+            ErrorKind::Tls,
+            ErrorKind::Connect,
+            ErrorKind::Request,
+        ] {
+            assert_eq!(kind.status(), StatusCode::BAD_GATEWAY, "{}", kind);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_classify_connect_errors() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let request = Clients::default()
+            .get(Some(Duration::from_secs(1)))
+            .get(format!("http://{}/", addr));
+        let error = send(request, None).await.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Refused);
+
+        let request = Clients::default().get(None).get("http://local-rs.invalid/");
+        let error = send(request, None).await.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Dns);
+    }
+
+    #[test]
+    fn test_hints_name_the_upstream() {
+        assert_eq!(
+            ErrorKind::Refused.hint("http://127.0.0.1:8081").unwrap(),
+            "Is your backend running on 127.0.0.1:8081?"
+        );
+        assert!(
+            ErrorKind::Tls
+                .hint("https://api.test")
+                .unwrap()
+                .contains("api.test:443")
+        );
+        assert_eq!(ErrorKind::Request.hint("http://127.0.0.1:8081"), None);
+    }
+
+    #[test]
+    fn test_clients_are_shared_per_connect_timeout() {
+        let clients = Clients::default();
+        clients.get(None);
+        clients.get(Some(Duration::from_secs(1)));
+        clients.get(Some(Duration::from_secs(1)));
+        assert_eq!(clients.clients.lock().unwrap().len(), 2);
+    }
+}
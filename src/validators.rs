@@ -0,0 +1,214 @@
+//! Cache validators (RFC 9110 §8.8) and conditional requests for static files.
+
+use axum::http::{HeaderMap, HeaderValue, header};
+use std::{
+    fs::Metadata,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The `ETag` and `Last-Modified` of a static file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// Strong entity tag derived from the file size and modification time
+    pub etag: Option<String>,
+    /// Modification time of the file
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Derives validators from file metadata without reading the file
+    ///
+    /// The ETag changes whenever the size or the (nanosecond) modification
+    /// time does, which is cheap and precise enough for local assets.
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        let last_modified = metadata.modified().ok();
+        let etag = last_modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|mtime| format!("\"{:x}-{:x}\"", metadata.len(), mtime.as_nanos()));
+
+        Validators {
+            etag,
+            last_modified,
+        }
+    }
+
+    /// Validators for a body derived from the file rather than the file itself
+    ///
+    /// The ETag is weakened and tagged with `suffix`, so it never matches the
+    /// raw file's ETag and a cached copy of one body is not revalidated as
+    /// the other.
+    pub fn derived(mut self, suffix: &str) -> Self {
+        self.etag = self.etag.map(|etag| {
+            format!(
+                "W/\"{}-{}\"",
+                etag.trim_start_matches("W/").trim_matches('"'),
+                suffix
+            )
+        });
+        self
+    }
+
+    /// Whether the client's cached copy is current, so a 304 can be sent
+    ///
+    /// `If-None-Match` takes precedence; `If-Modified-Since` is only
+    /// consulted without it, as RFC 9110 §13.2.2 requires.
+    pub fn is_not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+            return self.etag.as_deref().is_some_and(|etag| {
+                if_none_match.trim() == "*"
+                    || if_none_match
+                        .split(',')
+                        .any(|candidate| weak_eq(candidate.trim(), etag))
+            });
+        }
+
+        match (
+            header_str(headers, header::IF_MODIFIED_SINCE)
+                .and_then(|since| httpdate::parse_http_date(since.trim()).ok()),
+            self.last_modified,
+        ) {
+            (Some(since), Some(modified)) => truncate_to_seconds(modified) <= since,
+            _ => false,
+        }
+    }
+
+    /// Whether an `If-Range` precondition still holds for this file
+    ///
+    /// Entity tags must match strongly; HTTP-dates must equal the modification
+    /// time exactly (at second precision).
+    pub fn if_range_matches(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+        if if_range.starts_with('"') || if_range.starts_with("W/") {
+            return !if_range.starts_with("W/") && self.etag.as_deref() == Some(if_range);
+        }
+
+        match (httpdate::parse_http_date(if_range), self.last_modified) {
+            (Ok(date), Some(modified)) => truncate_to_seconds(modified) == date,
+            _ => false,
+        }
+    }
+
+    /// Adds `ETag` and `Last-Modified` to a response
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if let Some(etag) = self
+            .etag
+            .as_deref()
+            .and_then(|e| HeaderValue::from_str(e).ok())
+        {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Some(modified) = self.last_modified {
+            headers.insert(
+                header::LAST_MODIFIED,
+                HeaderValue::from_str(&httpdate::fmt_http_date(modified)).unwrap(),
+            );
+        }
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Weak comparison: equal opaque tags, ignoring any `W/` prefix
+fn weak_eq(a: &str, b: &str) -> bool {
+    a.trim_start_matches("W/") == b.trim_start_matches("W/")
+}
+
+/// Drops sub-second precision, which HTTP-dates cannot express
+fn truncate_to_seconds(time: SystemTime) -> SystemTime {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| UNIX_EPOCH + std::time::Duration::from_secs(since.as_secs()))
+        .unwrap_or(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn validators() -> Validators {
+        Validators {
+            etag: Some("\"24-abc\"".to_string()),
+            last_modified: Some(UNIX_EPOCH + Duration::from_millis(1_700_000_000_500)),
+        }
+    }
+
+    fn headers(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let validators = validators();
+        for value in ["\"24-abc\"", "W/\"24-abc\"", "\"other\", \"24-abc\"", "*"] {
+            assert!(
+                validators.is_not_modified(&headers(header::IF_NONE_MATCH, value)),
+                "{}",
+                value
+            );
+        }
+        assert!(!validators.is_not_modified(&headers(header::IF_NONE_MATCH, "\"other\"")));
+        assert!(!validators.is_not_modified(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_derived_etag_is_weak_and_distinct() {
+        let derived = validators().derived("lr");
+        assert_eq!(derived.etag.as_deref(), Some("W/\"24-abc-lr\""));
+        assert!(derived.is_not_modified(&headers(header::IF_NONE_MATCH, "W/\"24-abc-lr\"")));
+        assert!(!derived.is_not_modified(&headers(header::IF_NONE_MATCH, "\"24-abc\"")));
+        assert!(!validators().is_not_modified(&headers(header::IF_NONE_MATCH, "W/\"24-abc-lr\"")));
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let validators = validators();
+        let exact = httpdate::fmt_http_date(validators.last_modified.unwrap());
+        assert!(validators.is_not_modified(&headers(header::IF_MODIFIED_SINCE, &exact)));
+        assert!(validators.is_not_modified(&headers(
+            header::IF_MODIFIED_SINCE,
+            "Fri, 01 Jan 2100 00:00:00 GMT"
+        )));
+        assert!(!validators.is_not_modified(&headers(
+            header::IF_MODIFIED_SINCE,
+            "Thu, 01 Jan 2015 00:00:00 GMT"
+        )));
+    }
+
+    #[test]
+    fn test_if_none_match_takes_precedence() {
+        let mut headers = headers(header::IF_NONE_MATCH, "\"other\"");
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Fri, 01 Jan 2100 00:00:00 GMT"),
+        );
+        assert!(!validators().is_not_modified(&headers));
+    }
+
+    #[test]
+    fn test_if_range_matches() {
+        let validators = validators();
+        let date = httpdate::fmt_http_date(validators.last_modified.unwrap());
+
+        assert!(validators.if_range_matches("\"24-abc\""));
+        assert!(validators.if_range_matches(&date));
+        assert!(!validators.if_range_matches("W/\"24-abc\""));
+        assert!(!validators.if_range_matches("\"other\""));
+        assert!(!validators.if_range_matches("Thu, 01 Jan 2015 00:00:00 GMT"));
+        assert!(!Validators::default().if_range_matches(&date));
+    }
+
+    #[test]
+    fn test_apply_sets_headers() {
+        let mut headers = HeaderMap::new();
+        validators().apply(&mut headers);
+        assert_eq!(headers[header::ETAG], "\"24-abc\"");
+        assert_eq!(
+            headers[header::LAST_MODIFIED],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+    }
+}
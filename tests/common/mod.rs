@@ -0,0 +1,61 @@
+//! Setup shared by the integration tests
+
+// Each test crate compiles this module on its own and uses only part of it
+#![allow(dead_code)]
+
+use axum::Router;
+use local_rs::forwarded::ClientAddr;
+use std::{future::Future, path::PathBuf};
+use tokio::task::JoinHandle;
+
+/// Serves `app` on a random local port and returns its address
+pub async fn spawn(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    addr.to_string()
+}
+
+/// Like [`spawn`], but with each request's [`ClientAddr`] in its extensions
+pub async fn spawn_with_connect_info(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let app = app.into_make_service_with_connect_info::<ClientAddr>();
+        axum::serve(listener, app).await.unwrap();
+    });
+    addr.to_string()
+}
+
+/// A local address nothing listens on
+pub async fn unused_addr() -> String {
+    // Reserve a port, then free it
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Serves `app` on a random local port until `shutdown` resolves, returning
+/// its address and the server task
+pub async fn spawn_with_shutdown(
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> (String, JoinHandle<()>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await
+            .unwrap();
+    });
+    (addr.to_string(), server)
+}
+
+/// The shared, empty static dir for tests that only exercise proxying
+pub async fn test_static_dir() -> PathBuf {
+    let static_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_static");
+    tokio::fs::create_dir_all(&static_dir).await.unwrap();
+    static_dir
+}
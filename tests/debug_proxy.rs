@@ -8,7 +8,7 @@ use axum::{
     response::Response,
     routing::{any, get},
 };
-use local_rs::{handlers::proxy_api, state::AppState};
+use local_rs::{handlers::proxy_api, routes::ProxyRoute, state::AppState};
 use std::{path::PathBuf, sync::Arc};
 use tokio::time::{Duration, sleep};
 
@@ -44,10 +44,13 @@ async fn test_simple_proxy() {
 
     let api_path = "/api".to_string();
     let state = Arc::new(AppState {
-        api_base_url: format!("http://{}", backend_addr),
-        api_path: api_path.trim_end_matches('/').to_string(),
+        routes: vec![ProxyRoute::new(
+            "API",
+            &api_path,
+            &format!("http://{}", backend_addr),
+        )],
         static_dir: static_dir.clone(),
-        client: reqwest::Client::new(),
+        ..Default::default()
     });
 
     println!("Creating proxy app");
@@ -0,0 +1,110 @@
+//! Integration tests for the Prometheus metrics endpoint
+
+use axum::{Router, http::StatusCode, routing::post};
+use local_rs::metrics::{METRICS_PATH, Metrics};
+use local_rs::router::build_router;
+use local_rs::routes::ProxyRoute;
+use local_rs::state::AppState;
+use std::{path::PathBuf, sync::Arc};
+
+mod common;
+use common::spawn;
+
+#[tokio::test]
+async fn test_metrics_count_proxy_and_static_traffic() {
+    let backend_addr = spawn(Router::new().route(
+        "/pz/echo",
+        post(|body: axum::body::Bytes| async move { body }),
+    ))
+    .await;
+
+    let down_addr = common::unused_addr().await;
+
+    let static_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("test_static_metrics");
+    tokio::fs::create_dir_all(&static_dir).await.unwrap();
+    tokio::fs::write(static_dir.join("app.js"), "console.log(1)")
+        .await
+        .unwrap();
+
+    let state = Arc::new(AppState {
+        routes: vec![
+            ProxyRoute::new("API", "/pz", &backend_addr),
+            ProxyRoute::new("DOWN", "/down", &down_addr),
+        ],
+        static_dir,
+        metrics: Some(Metrics::default()),
+        ..Default::default()
+    });
+    let addr = spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    let echoed = client
+        .post(format!("http://{}/pz/echo", addr))
+        .body("0123456789")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(echoed.text().await.unwrap(), "0123456789");
+    let asset = reqwest::get(format!("http://{}/app.js", addr))
+        .await
+        .unwrap();
+    assert_eq!(asset.text().await.unwrap(), "console.log(1)");
+    let failed = reqwest::get(format!("http://{}/down/x", addr))
+        .await
+        .unwrap();
+    assert_eq!(failed.status(), StatusCode::BAD_GATEWAY);
+    // App paths that merely look internal are still counted
+    let missing = reqwest::get(format!("http://{}/__localization/en.json", addr))
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+    let response = reqwest::get(format!("http://{}{}", addr, METRICS_PATH))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let text = response.text().await.unwrap();
+
+    for line in [
+        "local_rs_requests_total{route=\"API\",method=\"POST\",status=\"200\"} 1",
+        // The scrape itself is not counted as a static request
+        "local_rs_requests_total{route=\"STATIC\",method=\"GET\",status=\"200\"} 1",
+        "local_rs_requests_total{route=\"DOWN\",method=\"GET\",status=\"502\"} 1",
+        "local_rs_requests_total{route=\"STATIC\",method=\"GET\",status=\"404\"} 1",
+        "local_rs_request_duration_seconds_count{kind=\"proxy\",route=\"API\"} 1",
+        "local_rs_request_duration_seconds_count{kind=\"static\",route=\"STATIC\"} 2",
+        "local_rs_upstream_errors_total{route=\"DOWN\",kind=\"refused\"} 1",
+        "local_rs_received_bytes_total{kind=\"proxy\"} 10",
+        "local_rs_in_flight_requests{kind=\"proxy\"} 0",
+    ] {
+        assert!(
+            text.lines().any(|l| l == line),
+            "missing {}\n{}",
+            line,
+            text
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_is_opt_in() {
+    let static_dir = common::test_static_dir().await;
+    let addr = spawn(build_router(Arc::new(AppState {
+        static_dir,
+        ..Default::default()
+    })))
+    .await;
+
+    let response = reqwest::get(format!("http://{}{}", addr, METRICS_PATH))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
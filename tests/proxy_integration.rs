@@ -10,9 +10,13 @@ use axum::{
 };
 use local_rs::handlers::{proxy_api, serve_static};
 use local_rs::middleware::log_requests;
+use local_rs::router::build_router;
+use local_rs::routes::ProxyRoute;
 use local_rs::state::AppState;
 use std::{path::PathBuf, sync::Arc};
 
+mod common;
+
 #[tokio::test]
 async fn test_proxy_backend_unavailable() {
     // Use a non-existent backend address
@@ -20,16 +24,18 @@ async fn test_proxy_backend_unavailable() {
     tokio::fs::create_dir_all(&static_dir).await.unwrap();
 
     let state = Arc::new(AppState {
-        api_base_url: "http://127.0.0.1:99999".to_string(), // Non-existent port
-        api_path: "/api".to_string(),
+        routes: vec![ProxyRoute::new("API", "/api", "http://127.0.0.1:99999")], // Non-existent port
         static_dir: static_dir.clone(),
-        client: reqwest::Client::new(),
+        ..Default::default()
     });
 
     let proxy_app = Router::new()
         .route("/api/{*path}", any(proxy_api))
         .fallback(get(serve_static))
-        .layer(axum_middleware::from_fn(log_requests))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            log_requests,
+        ))
         .with_state(state);
 
     let proxy_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -44,7 +50,7 @@ async fn test_proxy_backend_unavailable() {
 
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("http://{}/api/test", proxy_addr))
+        .get(format!("http://{}/api/test", proxy_addr))
         .send()
         .await
         .unwrap();
@@ -99,16 +105,22 @@ async fn test_proxy_with_mock_backend() {
     tokio::fs::create_dir_all(&static_dir).await.unwrap();
 
     let state = Arc::new(AppState {
-        api_base_url: format!("http://{}", backend_addr),
-        api_path: "/api".to_string(),
+        routes: vec![ProxyRoute::new(
+            "API",
+            "/api",
+            &format!("http://{}", backend_addr),
+        )],
         static_dir: static_dir.clone(),
-        client: reqwest::Client::new(),
+        ..Default::default()
     });
 
     let proxy_app = Router::new()
         .route("/api/{*path}", any(proxy_api))
         .fallback(get(serve_static))
-        .layer(axum_middleware::from_fn(log_requests))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            log_requests,
+        ))
         .with_state(state);
 
     let proxy_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -125,7 +137,7 @@ async fn test_proxy_with_mock_backend() {
 
     // Test GET request
     let response = client
-        .get(&format!("http://{}/api/test", proxy_addr))
+        .get(format!("http://{}/api/test", proxy_addr))
         .header("x-custom", "test-value")
         .send()
         .await
@@ -142,7 +154,7 @@ async fn test_proxy_with_mock_backend() {
     // Test POST request with body
     let request_body = "{\"name\": \"test\", \"value\": 123}";
     let response = client
-        .post(&format!("http://{}/api/echo", proxy_addr))
+        .post(format!("http://{}/api/echo", proxy_addr))
         .body(request_body)
         .header("content-type", "application/json")
         .send()
@@ -182,16 +194,22 @@ async fn test_proxy_query_parameters() {
     tokio::fs::create_dir_all(&static_dir).await.unwrap();
 
     let state = Arc::new(AppState {
-        api_base_url: format!("http://{}", backend_addr),
-        api_path: "/api".to_string(),
+        routes: vec![ProxyRoute::new(
+            "API",
+            "/api",
+            &format!("http://{}", backend_addr),
+        )],
         static_dir: static_dir.clone(),
-        client: reqwest::Client::new(),
+        ..Default::default()
     });
 
     let proxy_app = Router::new()
         .route("/api/{*path}", any(proxy_api))
         .fallback(get(serve_static))
-        .layer(axum_middleware::from_fn(log_requests))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            log_requests,
+        ))
         .with_state(state);
 
     let proxy_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -205,7 +223,7 @@ async fn test_proxy_query_parameters() {
 
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!(
+        .get(format!(
             "http://{}/api/search?q=test&page=2&limit=10",
             proxy_addr
         ))
@@ -245,16 +263,22 @@ async fn test_proxy_header_filtering() {
     tokio::fs::create_dir_all(&static_dir).await.unwrap();
 
     let state = Arc::new(AppState {
-        api_base_url: format!("http://{}", backend_addr),
-        api_path: "/api".to_string(),
+        routes: vec![ProxyRoute::new(
+            "API",
+            "/api",
+            &format!("http://{}", backend_addr),
+        )],
         static_dir: static_dir.clone(),
-        client: reqwest::Client::new(),
+        ..Default::default()
     });
 
     let proxy_app = Router::new()
         .route("/api/{*path}", any(proxy_api))
         .fallback(get(serve_static))
-        .layer(axum_middleware::from_fn(log_requests))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            log_requests,
+        ))
         .with_state(state);
 
     let proxy_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -268,7 +292,7 @@ async fn test_proxy_header_filtering() {
 
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("http://{}/api/headers", proxy_addr))
+        .get(format!("http://{}/api/headers", proxy_addr))
         .header("host", "should-be-filtered.com")
         .header("connection", "keep-alive")
         .header("accept-encoding", "gzip")
@@ -281,9 +305,9 @@ async fn test_proxy_header_filtering() {
 
     // Hop-by-hop headers should be filtered out (except those re-added by reqwest like host)
     assert!(response.headers().get("connection").is_none());
-    assert!(response.headers().get("accept-encoding").is_none());
 
-    // Custom headers should be preserved
+    // End-to-end headers like accept-encoding and custom headers should be preserved
+    assert_eq!(response.headers().get("accept-encoding").unwrap(), "gzip");
     assert_eq!(
         response.headers().get("x-custom").unwrap(),
         "should-preserve"
@@ -316,16 +340,22 @@ async fn test_proxy_error_propagation() {
     tokio::fs::create_dir_all(&static_dir).await.unwrap();
 
     let state = Arc::new(AppState {
-        api_base_url: format!("http://{}", backend_addr),
-        api_path: "/api".to_string(),
+        routes: vec![ProxyRoute::new(
+            "API",
+            "/api",
+            &format!("http://{}", backend_addr),
+        )],
         static_dir: static_dir.clone(),
-        client: reqwest::Client::new(),
+        ..Default::default()
     });
 
     let proxy_app = Router::new()
         .route("/api/{*path}", any(proxy_api))
         .fallback(get(serve_static))
-        .layer(axum_middleware::from_fn(log_requests))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            log_requests,
+        ))
         .with_state(state);
 
     let proxy_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
@@ -339,7 +369,7 @@ async fn test_proxy_error_propagation() {
 
     let client = reqwest::Client::new();
     let response = client
-        .get(&format!("http://{}/api/error", proxy_addr))
+        .get(format!("http://{}/api/error", proxy_addr))
         .send()
         .await
         .unwrap();
@@ -351,3 +381,711 @@ async fn test_proxy_error_propagation() {
     );
     assert_eq!(response.text().await.unwrap(), "Backend error");
 }
+
+#[tokio::test]
+async fn test_proxy_websocket_upgrade() {
+    use axum::extract::ws::{Message as WsMessage, WebSocketUpgrade};
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    // Backend echoes every text message back with a prefix
+    let backend_app = Router::new().route(
+        "/api/ws",
+        get(|ws: WebSocketUpgrade| async move {
+            ws.on_upgrade(|mut socket| async move {
+                while let Some(Ok(WsMessage::Text(text))) = socket.recv().await {
+                    let reply = format!("echo: {}", text.as_str());
+                    if socket.send(WsMessage::Text(reply.into())).await.is_err() {
+                        break;
+                    }
+                }
+            })
+        }),
+    );
+
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new(
+            "API",
+            "/api",
+            &format!("http://{}", backend_addr),
+        )],
+        static_dir: static_dir.clone(),
+        ..Default::default()
+    });
+
+    let proxy_app = Router::new()
+        .route("/api/{*path}", any(proxy_api))
+        .fallback(get(serve_static))
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            log_requests,
+        ))
+        .with_state(state);
+
+    let proxy_addr = common::spawn(proxy_app).await;
+
+    let (mut socket, response) =
+        tokio_tungstenite::connect_async(format!("ws://{}/api/ws", proxy_addr))
+            .await
+            .unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+    for text in ["hello", "world"] {
+        socket.send(Message::text(text)).await.unwrap();
+        let reply = socket.next().await.unwrap().unwrap();
+        assert_eq!(
+            reply.into_text().unwrap().as_str(),
+            format!("echo: {}", text)
+        );
+    }
+
+    socket.close(None).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_proxy_multiple_routes() {
+    // Each backend echoes the path it received, tagged with its own name
+    async fn spawn_echo_backend(name: &'static str) -> String {
+        let app = Router::new().fallback(move |uri: axum::http::Uri| async move {
+            format!("{} {}", name, uri.path())
+        });
+        common::spawn(app).await
+    }
+
+    let auth_addr = spawn_echo_backend("auth").await;
+    let core_addr = spawn_echo_backend("core").await;
+    let files_addr = spawn_echo_backend("files").await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let state = Arc::new(AppState {
+        routes: vec![
+            format!("/auth={},strip", auth_addr).parse().unwrap(),
+            ProxyRoute::new("API", "/api", &core_addr),
+            format!("/api/files={},label=FILES", files_addr)
+                .parse()
+                .unwrap(),
+        ],
+        static_dir,
+        ..Default::default()
+    });
+
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    for (path, expected) in [
+        ("/auth/login", "auth /login"),
+        ("/api/users/1", "core /api/users/1"),
+        ("/api/files/report.pdf", "files /api/files/report.pdf"),
+    ] {
+        let response = client
+            .get(format!("http://{}{}", proxy_addr, path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), expected);
+    }
+
+    // Paths outside every prefix fall through to static serving
+    let response = client
+        .get(format!("http://{}/authz/missing", proxy_addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_proxy_path_rewrites() {
+    let backend_app = Router::new().fallback(|uri: axum::http::Uri| async move {
+        format!("{}?{}", uri.path(), uri.query().unwrap_or(""))
+    });
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let mut route = ProxyRoute::new("API", "/api", &backend_addr);
+    route.rewrites = vec![
+        "strip:/api/v1".parse().unwrap(),
+        r"regex:^/api/v(\d+)/(.*)$=>/$2/v$1".parse().unwrap(),
+    ];
+    let state = Arc::new(AppState {
+        routes: vec![route],
+        static_dir,
+        ..Default::default()
+    });
+
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    for (path, expected) in [
+        ("/api/v1/users?page=2", "/users?page=2"),
+        ("/api/v3/users/7", "/users/7/v3?"),
+        ("/api/health", "/api/health?"),
+    ] {
+        let response = client
+            .get(format!("http://{}{}", proxy_addr, path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), expected);
+    }
+}
+
+#[tokio::test]
+async fn test_compression_for_static_and_proxied_responses() {
+    use local_rs::compression::CompressionConfig;
+    use local_rs::encoding::Encoding;
+
+    let payload = "{\"items\": [".to_string() + &"1,".repeat(2000) + "1]}";
+    let backend_payload = payload.clone();
+    let backend_app = Router::new()
+        .route(
+            "/api/items",
+            get(move || async move {
+                let mut response = Response::new(Body::from(backend_payload));
+                response.headers_mut().insert(
+                    "content-type",
+                    header::HeaderValue::from_static("application/json"),
+                );
+                response
+            }),
+        )
+        .route(
+            "/api/encoded",
+            get(|request: Request<Body>| async move {
+                // Backends that compress themselves see the client's preference
+                let accept = request.headers()["accept-encoding"].clone();
+                let mut response = Response::new(Body::from("already-encoded"));
+                response
+                    .headers_mut()
+                    .insert("content-encoding", header::HeaderValue::from_static("gzip"));
+                response.headers_mut().insert("x-accept-encoding", accept);
+                response
+            }),
+        );
+
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("test_static_compression");
+    let _ = tokio::fs::remove_dir_all(&static_dir).await;
+    tokio::fs::create_dir_all(&static_dir).await.unwrap();
+    tokio::fs::write(static_dir.join("app.js"), "let x = 1;\n".repeat(500))
+        .await
+        .unwrap();
+    tokio::fs::write(static_dir.join("tiny.js"), "let x = 1;")
+        .await
+        .unwrap();
+
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new("API", "/api", &backend_addr)],
+        static_dir,
+        compression: Some(CompressionConfig::new(vec![Encoding::Gzip], 1024, vec![])),
+        ..Default::default()
+    });
+
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        client
+            .get(format!("http://{}{}", proxy_addr, path))
+            .header("accept-encoding", "br, gzip")
+            .send()
+    };
+
+    // Only the enabled algorithm is used, for proxied and static bodies alike
+    for (path, original_len) in [("/api/items", payload.len()), ("/app.js", 5500)] {
+        let response = get(path).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip", "{}", path);
+        let compressed = response.bytes().await.unwrap();
+        assert!(compressed.len() < original_len / 4, "{}", path);
+    }
+
+    // Bodies under the minimum size stay uncompressed
+    let response = get("/tiny.js").await.unwrap();
+    assert!(response.headers().get("content-encoding").is_none());
+    assert_eq!(response.text().await.unwrap(), "let x = 1;");
+
+    // Backend-encoded bodies pass through untouched
+    let response = get("/api/encoded").await.unwrap();
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+    assert_eq!(response.headers()["x-accept-encoding"], "br, gzip");
+    assert_eq!(response.text().await.unwrap(), "already-encoded");
+}
+
+#[tokio::test]
+async fn test_compressed_static_responses_get_a_weak_etag() {
+    use local_rs::compression::CompressionConfig;
+    use local_rs::encoding::Encoding;
+
+    let static_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("test_static_compression_etag");
+    let _ = tokio::fs::remove_dir_all(&static_dir).await;
+    tokio::fs::create_dir_all(&static_dir).await.unwrap();
+    tokio::fs::write(static_dir.join("app.js"), "let x = 1;\n".repeat(500))
+        .await
+        .unwrap();
+
+    let state = Arc::new(AppState {
+        static_dir,
+        compression: Some(CompressionConfig::new(vec![Encoding::Gzip], 1024, vec![])),
+        ..Default::default()
+    });
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/app.js", proxy_addr);
+
+    let identity = client
+        .get(&url)
+        .header("accept-encoding", "identity")
+        .send()
+        .await
+        .unwrap();
+    assert!(identity.headers().get("content-encoding").is_none());
+    let identity_etag = identity.headers()["etag"].to_str().unwrap().to_string();
+    assert!(identity_etag.starts_with('"'));
+
+    let gzip = client
+        .get(&url)
+        .header("accept-encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(gzip.headers()["content-encoding"], "gzip");
+    let gzip_etag = gzip.headers()["etag"].to_str().unwrap().to_string();
+    assert_ne!(gzip_etag, identity_etag);
+    assert_eq!(gzip_etag, format!("W/{}", identity_etag));
+
+    // The weak tag still revalidates the compressed copy
+    let response = client
+        .get(&url)
+        .header("accept-encoding", "gzip")
+        .header("if-none-match", &gzip_etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn test_proxy_forwarded_headers() {
+    use local_rs::forwarded::{ForwardedConfig, TrustMode};
+
+    // The backend echoes the forwarding headers it received
+    let backend_app = Router::new().fallback(|headers: header::HeaderMap| async move {
+        [
+            "x-forwarded-for",
+            "x-forwarded-proto",
+            "x-forwarded-host",
+            "x-forwarded-prefix",
+            "forwarded",
+        ]
+        .iter()
+        .map(|name| {
+            let value = headers.get(*name).map(|v| v.to_str().unwrap());
+            format!("{}={}", name, value.unwrap_or("-"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+    });
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let mut auth = ProxyRoute::new("AUTH", "/auth", &backend_addr);
+    auth.strip_prefix = true;
+    let state = Arc::new(AppState {
+        routes: vec![auth],
+        static_dir,
+        forwarded: ForwardedConfig {
+            trust: TrustMode::Append,
+            rfc7239: true,
+        },
+        ..Default::default()
+    });
+
+    let proxy_addr = common::spawn_with_connect_info(build_router(state)).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/auth/login", proxy_addr))
+        .header("x-forwarded-for", "203.0.113.1")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.text().await.unwrap(),
+        format!(
+            "x-forwarded-for=203.0.113.1, 127.0.0.1\n\
+             x-forwarded-proto=http\n\
+             x-forwarded-host={addr}\n\
+             x-forwarded-prefix=/auth\n\
+             forwarded=for=127.0.0.1;proto=http;host=\"{addr}\"",
+            addr = proxy_addr
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_proxy_host_handling() {
+    // The backend echoes the Host it received, like a virtual-host router would see it
+    let backend_app = Router::new().fallback(|headers: header::HeaderMap| async move {
+        headers[header::HOST].to_str().unwrap().to_string()
+    });
+    let upstream = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let plain = ProxyRoute::new("PLAIN", "/plain", &upstream);
+    let mut preserved = ProxyRoute::new("PRESERVED", "/preserved", &upstream);
+    preserved.preserve_host = true;
+    let mut overridden = ProxyRoute::new("OVERRIDDEN", "/overridden", &upstream);
+    overridden.preserve_host = true;
+    overridden.host_override = Some(header::HeaderValue::from_static("tenant-b.test"));
+    let state = Arc::new(AppState {
+        routes: vec![plain, preserved, overridden],
+        static_dir,
+        ..Default::default()
+    });
+
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    for (path, expected) in [
+        ("/plain/x", upstream.as_str()),
+        ("/preserved/x", "tenant-a.test"),
+        ("/overridden/x", "tenant-b.test"),
+    ] {
+        let response = client
+            .get(format!("http://{}{}", proxy_addr, path))
+            .header(header::HOST, "tenant-a.test")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), expected, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_streams_large_upload() {
+    use futures_util::StreamExt;
+    use std::io;
+    use tokio::sync::Notify;
+
+    const CHUNK: usize = 1024 * 1024;
+    const CHUNKS: usize = 64;
+
+    // The backend counts the bytes as they arrive and reports the total
+    let received_some = Arc::new(Notify::new());
+    let notify = received_some.clone();
+    let backend_app = Router::new().fallback(move |body: Body| async move {
+        let mut stream = body.into_data_stream();
+        let mut total = 0;
+        while let Some(chunk) = stream.next().await {
+            total += chunk.unwrap().len();
+            notify.notify_one();
+        }
+        total.to_string()
+    });
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new("API", "/pz", &backend_addr)],
+        static_dir,
+        ..Default::default()
+    });
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    // Upload through a channel so the client can wait on the backend mid-body
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Result<Vec<u8>, io::Error>>(1);
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    let upload = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("http://{}/pz/upload", proxy_addr))
+            .body(reqwest::Body::wrap_stream(body))
+            .send(),
+    );
+
+    sender.send(Ok(vec![b'x'; CHUNK])).await.unwrap();
+    // A buffering proxy would hold everything back until the upload ends
+    tokio::time::timeout(
+        tokio::time::Duration::from_secs(5),
+        received_some.notified(),
+    )
+    .await
+    .expect("backend saw nothing while the upload was in progress");
+    for _ in 1..CHUNKS {
+        sender.send(Ok(vec![b'x'; CHUNK])).await.unwrap();
+    }
+    drop(sender);
+
+    let response = upload.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), (CHUNK * CHUNKS).to_string());
+}
+
+#[tokio::test]
+async fn test_proxy_max_body_size() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let backend_hits = hits.clone();
+    let backend_app = Router::new().fallback(move |body: axum::body::Bytes| async move {
+        backend_hits.fetch_add(1, Ordering::SeqCst);
+        body.len().to_string()
+    });
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new("API", "/pz", &backend_addr)],
+        static_dir,
+        max_body_size: Some(1024),
+        ..Default::default()
+    });
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/pz/upload", proxy_addr);
+
+    // At the limit passes through
+    let response = client.post(&url).body(vec![0; 1024]).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "1024");
+
+    // A declared Content-Length over the limit is refused before connecting
+    let response = client.post(&url).body(vec![0; 4096]).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // A chunked body is cut off once it goes over
+    let chunks: Vec<Result<_, std::io::Error>> = (0..4).map(|_| Ok(vec![0u8; 512])).collect();
+    let response = client
+        .post(&url)
+        .body(reqwest::Body::wrap_stream(futures_util::stream::iter(
+            chunks,
+        )))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_proxy_timeouts() {
+    use local_rs::upstream::Timeouts;
+    use std::time::Duration;
+
+    let backend_app = Router::new().fallback(|| async {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        "late"
+    });
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let mut first_byte = ProxyRoute::new("FIRST", "/first", &backend_addr);
+    first_byte.timeouts.first_byte = Some(Duration::from_millis(200));
+    let mut total = ProxyRoute::new("TOTAL", "/total", &backend_addr);
+    total.timeouts = Timeouts {
+        first_byte: None,
+        total: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    let state = Arc::new(AppState {
+        routes: vec![first_byte, total],
+        static_dir,
+        ..Default::default()
+    });
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    for path in ["/first/slow", "/total/slow"] {
+        let started = std::time::Instant::now();
+        let response = reqwest::get(format!("http://{}{}", proxy_addr, path))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT, "{}", path);
+        assert!(started.elapsed() < Duration::from_secs(1), "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_error_pages() {
+    let backend_addr = common::unused_addr().await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new("API", "/pz", &backend_addr)],
+        static_dir,
+        ..Default::default()
+    });
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/pz/users", proxy_addr);
+
+    // Browsers get an HTML page
+    let response = client
+        .get(&url)
+        .header(header::ACCEPT, "text/html,*/*")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains(&format!("http://{}/pz/users", backend_addr)));
+    assert!(html.contains("connection refused"));
+    assert!(html.contains(&format!("Is your backend running on {}?", backend_addr)));
+
+    // API clients get JSON
+    let response = client
+        .get(&url)
+        .header(header::ACCEPT, "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let json: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(json["status"], 502);
+    assert_eq!(json["error"], "connection refused");
+    assert!(!json["request_id"].as_str().unwrap().is_empty());
+
+    // Static misses use the same renderer
+    let response = client
+        .get(format!("http://{}/missing.js", proxy_addr))
+        .header(header::ACCEPT, "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let json: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(json["message"], "No file matches /missing.js");
+}
+
+#[tokio::test]
+async fn test_access_log_file() {
+    use local_rs::access_log::{AccessLogConfig, LogFormat};
+    use local_rs::log_file::Rotation;
+    use std::time::Duration;
+
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/test_access_log_file");
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+    tokio::fs::write(dir.join("app.js"), "console.log(1)")
+        .await
+        .unwrap();
+    let path = dir.join("access.log");
+
+    let access_log = AccessLogConfig {
+        path: path.clone(),
+        format: LogFormat::Json,
+        rotation: Rotation::Never,
+        keep: 0,
+    };
+    // Console stays pretty; the file gets its own format
+    let state = Arc::new(AppState {
+        static_dir: dir.clone(),
+        access_log_file: Some(access_log.open().unwrap()),
+        ..Default::default()
+    });
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let response = reqwest::get(format!("http://{}/app.js?v=2", proxy_addr))
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "console.log(1)");
+
+    // The line is written once the server has dropped the response body
+    let mut text = String::new();
+    for _ in 0..50 {
+        text = tokio::fs::read_to_string(&path).await.unwrap();
+        if !text.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let json: serde_json::Value = serde_json::from_str(text.trim_end()).unwrap();
+    assert_eq!(json["path"], "/app.js");
+    assert_eq!(json["query"], "v=2");
+    assert_eq!(json["route"], "STATIC");
+    assert_eq!(json["status"], 200);
+    assert_eq!(json["bytes"], 14);
+}
+
+#[tokio::test]
+async fn test_proxy_request_id_propagation() {
+    // The backend reports the request ID it received
+    let backend_app = Router::new().route(
+        "/pz/id",
+        get(|headers: axum::http::HeaderMap| async move {
+            headers
+                .get("x-request-id")
+                .map(|id| id.to_str().unwrap().to_string())
+                .unwrap_or_default()
+        }),
+    );
+    let backend_addr = common::spawn(backend_app).await;
+
+    let static_dir = common::test_static_dir().await;
+
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new("API", "/pz", &backend_addr)],
+        static_dir,
+        ..Default::default()
+    });
+    let proxy_addr = common::spawn(build_router(state)).await;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/pz/id", proxy_addr);
+
+    // A generated ID reaches the backend and comes back to the client
+    let response = client.get(&url).send().await.unwrap();
+    let returned = response.headers()["x-request-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(returned.len(), 5);
+    assert_eq!(response.text().await.unwrap(), returned);
+
+    // A client-supplied ID is reused end to end
+    let response = client
+        .get(&url)
+        .header("x-request-id", "trace-1234")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "trace-1234");
+    assert_eq!(response.text().await.unwrap(), "trace-1234");
+
+    // Static responses carry it too
+    let response = client
+        .get(format!("http://{}/missing.js", proxy_addr))
+        .header("x-request-id", "trace-5678")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "trace-5678");
+}
@@ -0,0 +1,166 @@
+//! Integration tests for HTTP Range requests against static files
+
+use axum::http::{StatusCode, header};
+use local_rs::router::build_router;
+use local_rs::state::AppState;
+use std::{path::PathBuf, sync::Arc};
+
+mod common;
+
+const CONTENT: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Serves a static dir holding `letters.txt` and returns the server address
+async fn spawn_range_server(name: &str) -> (String, PathBuf) {
+    let static_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join(name);
+    let _ = tokio::fs::remove_dir_all(&static_dir).await;
+    tokio::fs::create_dir_all(&static_dir).await.unwrap();
+    tokio::fs::write(static_dir.join("letters.txt"), CONTENT)
+        .await
+        .unwrap();
+
+    let state = Arc::new(AppState {
+        static_dir: static_dir.clone(),
+        ..Default::default()
+    });
+
+    let addr = common::spawn(build_router(state)).await;
+    (addr, static_dir)
+}
+
+async fn get_range(addr: &str, range: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("http://{}/letters.txt", addr))
+        .header(header::RANGE, range)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_full_response_advertises_ranges() {
+    let (addr, _) = spawn_range_server("test_range_full").await;
+
+    let response = reqwest::get(format!("http://{}/letters.txt", addr))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    assert_eq!(response.text().await.unwrap(), CONTENT);
+}
+
+#[tokio::test]
+async fn test_single_range() {
+    let (addr, _) = spawn_range_server("test_range_single").await;
+
+    let response = get_range(&addr, "bytes=10-15").await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 10-15/36");
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "6");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain");
+    assert_eq!(response.text().await.unwrap(), "abcdef");
+
+    // Suffix ranges resume from the end, as video players do when seeking
+    let response = get_range(&addr, "bytes=-4").await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 32-35/36");
+    assert_eq!(response.text().await.unwrap(), "wxyz");
+}
+
+#[tokio::test]
+async fn test_multiple_ranges() {
+    let (addr, _) = spawn_range_server("test_range_multi").await;
+
+    let response = get_range(&addr, "bytes=0-2, 30-").await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+
+    let content_type = response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .unwrap();
+    let length: usize = response.headers()[header::CONTENT_LENGTH]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let body = response.text().await.unwrap();
+    assert_eq!(body.len(), length);
+    assert_eq!(
+        body,
+        format!(
+            "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-2/36\r\n\r\n012\
+             \r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 30-35/36\r\n\r\nuvwxyz\
+             \r\n--{b}--\r\n",
+            b = boundary
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_unsatisfiable_range() {
+    let (addr, _) = spawn_range_server("test_range_unsatisfiable").await;
+
+    let response = get_range(&addr, "bytes=100-200").await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */36");
+
+    // Malformed ranges are ignored rather than rejected
+    let response = get_range(&addr, "bytes=5-1").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), CONTENT);
+}
+
+#[tokio::test]
+async fn test_if_range_validation() {
+    let (addr, static_dir) = spawn_range_server("test_range_if_range").await;
+    let modified = std::fs::metadata(static_dir.join("letters.txt"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    let client = reqwest::Client::new();
+
+    // Unchanged file: the range is honored
+    let response = client
+        .get(format!("http://{}/letters.txt", addr))
+        .header(header::RANGE, "bytes=0-3")
+        .header(header::IF_RANGE, httpdate::fmt_http_date(modified))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.text().await.unwrap(), "0123");
+
+    // A matching strong ETag works the same way
+    let etag = client
+        .get(format!("http://{}/letters.txt", addr))
+        .send()
+        .await
+        .unwrap()
+        .headers()[header::ETAG]
+        .clone();
+    let response = client
+        .get(format!("http://{}/letters.txt", addr))
+        .header(header::RANGE, "bytes=4-5")
+        .header(header::IF_RANGE, etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.text().await.unwrap(), "45");
+
+    // Stale validator: the whole, current file is sent instead
+    let response = client
+        .get(format!("http://{}/letters.txt", addr))
+        .header(header::RANGE, "bytes=0-3")
+        .header(header::IF_RANGE, "Thu, 01 Jan 2015 00:00:00 GMT")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), CONTENT);
+}
@@ -0,0 +1,87 @@
+//! Integration tests for config hot reloading
+
+use argh::FromArgs;
+use axum::{Router, http::StatusCode, routing::get};
+use local_rs::cli::Cli;
+use local_rs::config::Config;
+use local_rs::reload::Reloader;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+mod common;
+
+/// Starts a backend answering `/api/{*path}` with `name` after `delay`,
+/// signalling `received` as each request arrives
+async fn spawn_backend(name: &'static str, delay: Duration, received: Arc<Notify>) -> String {
+    let app = Router::new().route(
+        "/api/{*path}",
+        get(move || async move {
+            received.notify_one();
+            tokio::time::sleep(delay).await;
+            name
+        }),
+    );
+    common::spawn(app).await
+}
+
+/// Writes a config proxying `/api` to `backend` and returns its path
+async fn write_config(dir: &Path, backend: &str) -> PathBuf {
+    let path = dir.join("local.toml");
+    let config = format!(
+        "static-dir = \".\"\napi = \"{}\"\napi-path = \"/api\"\n",
+        backend
+    );
+    tokio::fs::write(&path, config).await.unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_reload_switches_backend_without_dropping_requests() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/test_reload");
+    tokio::fs::create_dir_all(&dir).await.unwrap();
+
+    let old_received = Arc::new(Notify::new());
+    let old_backend = spawn_backend("old", Duration::from_millis(500), old_received.clone()).await;
+    let new_backend = spawn_backend("new", Duration::ZERO, Arc::default()).await;
+    let path = write_config(&dir, &old_backend).await;
+
+    let cli = Cli::from_args(&["local-rs"], &["--config", path.to_str().unwrap()]).unwrap();
+    let config = Config::load(&cli).unwrap();
+    let mut reloader = Reloader::new(cli, config).unwrap();
+
+    let addr = common::spawn(reloader.router()).await;
+
+    // Starts on the old (slow) backend, then reloads while it is in flight
+    let in_flight = tokio::spawn(reqwest::get(format!("http://{}/api/slow", addr)));
+    old_received.notified().await;
+
+    write_config(&dir, &new_backend).await;
+    reloader.reload().unwrap();
+    assert_eq!(
+        reloader.config().routes[0].upstream,
+        format!("http://{}", new_backend)
+    );
+
+    let response = reqwest::get(format!("http://{}/api/fast", addr))
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "new");
+
+    let response = in_flight.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "old");
+
+    // An invalid file keeps the current config
+    tokio::fs::write(&path, "static-dir = \".\"\napi = \"localhost:99999\"\n")
+        .await
+        .unwrap();
+    assert!(reloader.reload().is_err());
+    let response = reqwest::get(format!("http://{}/api/fast", addr))
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "new");
+}
@@ -0,0 +1,155 @@
+//! Integration tests for graceful shutdown and connection draining
+
+use axum::{Router, http::StatusCode, middleware as axum_middleware, routing::get};
+use local_rs::live_reload::{LIVE_RELOAD_PATH, LiveReload};
+use local_rs::router::build_router;
+use local_rs::shutdown::{InFlight, track_in_flight};
+use local_rs::state::AppState;
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+mod common;
+
+/// Serves `app` behind the in-flight tracker until `shutdown` resolves
+async fn spawn_server(
+    app: Router,
+    in_flight: InFlight,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> (String, JoinHandle<()>) {
+    let app = app.layer(axum_middleware::from_fn_with_state(
+        in_flight,
+        track_in_flight,
+    ));
+    common::spawn_with_shutdown(app, shutdown).await
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_in_flight_requests() {
+    let app = Router::new().route(
+        "/slow",
+        get(|| async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            "done"
+        }),
+    );
+    let in_flight = InFlight::default();
+    let (stop, stopped) = oneshot::channel::<()>();
+    let (addr, server) = spawn_server(app, in_flight.clone(), async {
+        let _ = stopped.await;
+    })
+    .await;
+
+    let request = tokio::spawn(reqwest::get(format!("http://{}/slow", addr)));
+    while in_flight.count() == 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    stop.send(()).unwrap();
+
+    // The request started before shutdown still completes
+    let response = request.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "done");
+
+    tokio::time::timeout(Duration::from_secs(2), server)
+        .await
+        .expect("server did not finish draining")
+        .unwrap();
+    assert_eq!(in_flight.count(), 0);
+
+    // And new connections are refused
+    assert!(reqwest::get(format!("http://{}/slow", addr)).await.is_err());
+}
+
+#[tokio::test]
+async fn test_shutdown_ends_live_reload_streams() {
+    let static_dir = common::test_static_dir().await;
+
+    let state = Arc::new(AppState {
+        static_dir,
+        live_reload: Some(LiveReload::new()),
+        ..Default::default()
+    });
+    let token = state.shutdown.clone();
+    let in_flight = InFlight::default();
+    let (addr, server) = spawn_server(
+        build_router(state),
+        in_flight.clone(),
+        token.clone().cancelled_owned(),
+    )
+    .await;
+
+    let mut events = reqwest::get(format!("http://{}{}", addr, LIVE_RELOAD_PATH))
+        .await
+        .unwrap();
+    assert_eq!(in_flight.count(), 1);
+
+    token.cancel();
+    // The event stream ends instead of holding the connection open
+    while let Some(_chunk) = events.chunk().await.unwrap() {}
+
+    tokio::time::timeout(Duration::from_secs(2), server)
+        .await
+        .expect("server did not finish draining")
+        .unwrap();
+    assert_eq!(in_flight.count(), 0);
+}
+
+#[tokio::test]
+async fn test_shutdown_closes_websocket_tunnels() {
+    use axum::extract::ws::{Message as WsMessage, WebSocketUpgrade};
+    use futures_util::{SinkExt, StreamExt};
+    use local_rs::routes::ProxyRoute;
+    use tokio_tungstenite::tungstenite::{Message, protocol::frame::coding::CloseCode};
+
+    let backend_addr = common::spawn(Router::new().route(
+        "/api/ws",
+        get(|ws: WebSocketUpgrade| async move {
+            ws.on_upgrade(|mut socket| async move {
+                while let Some(Ok(WsMessage::Text(text))) = socket.recv().await {
+                    if socket.send(WsMessage::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+            })
+        }),
+    ))
+    .await;
+
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new("API", "/api", &backend_addr)],
+        static_dir: common::test_static_dir().await,
+        ..Default::default()
+    });
+    let token = state.shutdown.clone();
+    let in_flight = InFlight::default();
+    let (addr, server) = spawn_server(
+        build_router(state),
+        in_flight.clone(),
+        token.clone().cancelled_owned(),
+    )
+    .await;
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/api/ws", addr))
+        .await
+        .unwrap();
+    socket.send(Message::text("hello")).await.unwrap();
+    assert_eq!(
+        socket.next().await.unwrap().unwrap(),
+        Message::text("hello")
+    );
+    // The open tunnel counts as in flight once its 101 response is sent
+    assert_eq!(in_flight.count(), 1);
+
+    token.cancel();
+    let Message::Close(Some(frame)) = socket.next().await.unwrap().unwrap() else {
+        panic!("expected a close frame");
+    };
+    assert_eq!(frame.code, CloseCode::Away);
+
+    tokio::time::timeout(Duration::from_secs(2), async {
+        server.await.unwrap();
+        in_flight.drained().await;
+    })
+    .await
+    .expect("tunnel was not drained");
+}
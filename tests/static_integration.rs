@@ -0,0 +1,413 @@
+//! Integration tests for static file serving
+
+use axum::http::StatusCode;
+use futures_util::StreamExt;
+use local_rs::live_reload::{self, LIVE_RELOAD_PATH, LiveReload};
+use local_rs::router::build_router;
+use local_rs::state::AppState;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+mod common;
+
+/// Creates a fresh static directory under `target/` for a single test
+async fn create_static_dir(name: &str) -> PathBuf {
+    let static_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join(name);
+    let _ = tokio::fs::remove_dir_all(&static_dir).await;
+    tokio::fs::create_dir_all(&static_dir).await.unwrap();
+    static_dir
+}
+
+/// Starts a static-only server for the given state and returns its address
+async fn spawn_static_server(state: AppState) -> String {
+    common::spawn(build_router(Arc::new(state))).await
+}
+
+#[tokio::test]
+async fn test_spa_fallback_serves_index_for_client_routes() {
+    let static_dir = create_static_dir("test_static_spa").await;
+    tokio::fs::write(static_dir.join("index.html"), "<html>app</html>")
+        .await
+        .unwrap();
+
+    let addr = spawn_static_server(AppState {
+        static_dir: static_dir.clone(),
+        spa_fallback: Some(static_dir.join("index.html")),
+        ..Default::default()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+
+    // Deep link reload from the browser gets the app shell
+    let response = client
+        .get(format!("http://{}/users/42/settings", addr))
+        .header("accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
+    assert_eq!(response.text().await.unwrap(), "<html>app</html>");
+
+    // Missing assets still 404
+    let response = client
+        .get(format!("http://{}/app.js", addr))
+        .header("accept", "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Non-HTML requests (e.g. fetch calls) still 404
+    let response = client
+        .get(format!("http://{}/users/42", addr))
+        .header("accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_spa_fallback_custom_document() {
+    let static_dir = create_static_dir("test_static_spa_custom").await;
+    tokio::fs::write(static_dir.join("200.html"), "<html>shell</html>")
+        .await
+        .unwrap();
+
+    let addr = spawn_static_server(AppState {
+        static_dir: static_dir.clone(),
+        spa_fallback: Some(static_dir.join("200.html")),
+        ..Default::default()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/dashboard", addr))
+        .header("accept", "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "<html>shell</html>");
+}
+
+#[tokio::test]
+async fn test_missing_route_without_spa_mode_is_404() {
+    let static_dir = create_static_dir("test_static_no_spa").await;
+    tokio::fs::write(static_dir.join("index.html"), "<html>app</html>")
+        .await
+        .unwrap();
+
+    let addr = spawn_static_server(AppState {
+        static_dir,
+        ..Default::default()
+    })
+    .await;
+
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/users/42", addr))
+        .header("accept", "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_live_reload_injects_client_and_pushes_css_changes() {
+    let static_dir = create_static_dir("test_static_live_reload").await;
+    tokio::fs::write(
+        static_dir.join("index.html"),
+        "<html><body>app</body></html>",
+    )
+    .await
+    .unwrap();
+    tokio::fs::write(static_dir.join("app.css"), "body {}")
+        .await
+        .unwrap();
+
+    let reload = LiveReload::new();
+    let _watcher = live_reload::watch(&static_dir, reload.clone()).unwrap();
+    let addr = spawn_static_server(AppState {
+        static_dir: static_dir.clone(),
+        live_reload: Some(reload),
+        ..Default::default()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+
+    // HTML pages carry the client script, other assets are served untouched
+    let html = client
+        .get(format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(LIVE_RELOAD_PATH));
+    assert!(html.ends_with("</script>\n</body></html>"));
+
+    let css = client
+        .get(format!("http://{}/app.css", addr))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(css, "body {}");
+
+    let response = client
+        .get(format!("http://{}{}", addr, LIVE_RELOAD_PATH))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "text/event-stream"
+    );
+    let mut events = response.bytes_stream();
+
+    tokio::fs::write(static_dir.join("app.css"), "body { color: red }")
+        .await
+        .unwrap();
+
+    let event = tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("no reload event pushed")
+        .unwrap()
+        .unwrap();
+    assert_eq!(&event[..], b"data: css\n\n");
+}
+
+#[tokio::test]
+async fn test_live_reload_page_has_its_own_etag() {
+    let static_dir = create_static_dir("test_static_live_reload_etag").await;
+    tokio::fs::write(
+        static_dir.join("index.html"),
+        "<html><body>app</body></html>",
+    )
+    .await
+    .unwrap();
+
+    let plain = spawn_static_server(AppState {
+        static_dir: static_dir.clone(),
+        ..Default::default()
+    })
+    .await;
+    let injected = spawn_static_server(AppState {
+        static_dir: static_dir.clone(),
+        live_reload: Some(LiveReload::new()),
+        ..Default::default()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+    let etag = |addr: &str| {
+        let request = client.get(format!("http://{}/index.html", addr));
+        async move {
+            let response = request.send().await.unwrap();
+            response.headers()["etag"].to_str().unwrap().to_string()
+        }
+    };
+    let plain_etag = etag(&plain).await;
+    let injected_etag = etag(&injected).await;
+    assert!(!plain_etag.starts_with("W/"), "{}", plain_etag);
+    assert!(injected_etag.starts_with("W/"), "{}", injected_etag);
+    assert!(injected_etag.ends_with("-lr\""), "{}", injected_etag);
+
+    // A copy cached without the client is not revalidated once live reload is on
+    let response = client
+        .get(format!("http://{}/index.html", injected))
+        .header("if-none-match", &plain_etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains(LIVE_RELOAD_PATH));
+
+    // While the injected page itself still revalidates
+    let response = client
+        .get(format!("http://{}/index.html", injected))
+        .header("if-none-match", &injected_etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+/// Resident set size of the test process, where the platform exposes it
+fn resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096)
+}
+
+#[tokio::test]
+async fn test_large_file_is_streamed() {
+    const FILE_SIZE: u64 = 1024 * 1024 * 1024;
+
+    let static_dir = create_static_dir("test_static_large_file").await;
+    // Sparse, so the test does not need a gigabyte of disk
+    let file = std::fs::File::create(static_dir.join("dataset.bin")).unwrap();
+    file.set_len(FILE_SIZE).unwrap();
+
+    let addr = spawn_static_server(AppState {
+        static_dir: static_dir.clone(),
+        ..Default::default()
+    })
+    .await;
+
+    let memory_before = resident_memory();
+    let requested_at = std::time::Instant::now();
+
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/dataset.bin", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.content_length(), Some(FILE_SIZE));
+
+    let mut body = response.bytes_stream();
+    let first_chunk = body.next().await.unwrap().unwrap();
+    assert!(!first_chunk.is_empty());
+    assert!(
+        requested_at.elapsed() < Duration::from_secs(2),
+        "first byte took {:?}",
+        requested_at.elapsed()
+    );
+
+    // Reading the whole file up front would have grown the process by ~1 GiB
+    if let (Some(before), Some(after)) = (memory_before, resident_memory()) {
+        let growth = after.saturating_sub(before);
+        assert!(growth < 64 * 1024 * 1024, "memory grew by {} bytes", growth);
+    }
+
+    drop(body);
+    let _ = tokio::fs::remove_dir_all(&static_dir).await;
+}
+
+#[tokio::test]
+async fn test_conditional_get_returns_not_modified() {
+    let static_dir = create_static_dir("test_static_conditional").await;
+    tokio::fs::write(static_dir.join("app.js"), "console.log(1)")
+        .await
+        .unwrap();
+
+    let addr = spawn_static_server(AppState {
+        static_dir: static_dir.clone(),
+        ..Default::default()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/app.js", addr);
+
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    let last_modified = response.headers()["last-modified"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    // Revalidating with either validator skips the body
+    let response = client
+        .get(&url)
+        .header("if-none-match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()["etag"], etag.as_str());
+    assert!(response.text().await.unwrap().is_empty());
+
+    let response = client
+        .get(&url)
+        .header("if-modified-since", &last_modified)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // Changing the file invalidates the cached copy
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    tokio::fs::write(static_dir.join("app.js"), "console.log(2)")
+        .await
+        .unwrap();
+
+    let response = client
+        .get(&url)
+        .header("if-none-match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()["etag"], etag.as_str());
+    assert_eq!(response.text().await.unwrap(), "console.log(2)");
+}
+
+#[tokio::test]
+async fn test_precompressed_siblings_are_negotiated() {
+    let static_dir = create_static_dir("test_static_precompressed").await;
+    for (name, content) in [
+        ("app.js", "raw"),
+        ("app.js.br", "brotli"),
+        ("app.js.gz", "gzip"),
+        ("style.css", "body {}"),
+    ] {
+        tokio::fs::write(static_dir.join(name), content)
+            .await
+            .unwrap();
+    }
+
+    let addr = spawn_static_server(AppState {
+        static_dir,
+        ..Default::default()
+    })
+    .await;
+
+    let client = reqwest::Client::new();
+    for (accept_encoding, encoding, body) in [
+        ("gzip, deflate, br, zstd", Some("br"), "brotli"),
+        ("gzip, br;q=0.5", Some("gzip"), "gzip"),
+        ("zstd", None, "raw"),
+        ("identity", None, "raw"),
+    ] {
+        let response = client
+            .get(format!("http://{}/app.js", addr))
+            .header("accept-encoding", accept_encoding)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .headers()
+                .get("content-encoding")
+                .map(|value| value.to_str().unwrap()),
+            encoding,
+            "{}",
+            accept_encoding
+        );
+        assert_eq!(response.headers()["vary"], "accept-encoding");
+        assert_eq!(response.headers()["content-type"], "text/javascript");
+        assert_eq!(response.text().await.unwrap(), body);
+    }
+
+    // Files without siblings are served as-is
+    let response = client
+        .get(format!("http://{}/style.css", addr))
+        .header("accept-encoding", "br, gzip")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.headers().get("content-encoding").is_none());
+    assert_eq!(response.text().await.unwrap(), "body {}");
+}
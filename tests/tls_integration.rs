@@ -0,0 +1,65 @@
+//! Integration tests for TLS termination
+
+use axum::http::StatusCode;
+use local_rs::router::build_router;
+use local_rs::state::AppState;
+use local_rs::tls::{self, TlsListener};
+use std::{path::PathBuf, sync::Arc};
+
+#[tokio::test]
+async fn test_self_signed_tls_serves_https() {
+    let base = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("test_tls_integration");
+    let _ = tokio::fs::remove_dir_all(&base).await;
+    let static_dir = base.join("static");
+    tokio::fs::create_dir_all(&static_dir).await.unwrap();
+    tokio::fs::write(static_dir.join("index.html"), "<html>secure</html>")
+        .await
+        .unwrap();
+
+    let files = tls::self_signed(&base.join("certs"), "127.0.0.1".parse().unwrap()).unwrap();
+    let certs = tls::load_certs(&files.cert).unwrap();
+    let config = tls::server_config(certs, &files.key).unwrap();
+
+    let state = Arc::new(AppState {
+        static_dir,
+        ..Default::default()
+    });
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let listener = TlsListener::new(listener, Arc::new(config)).unwrap();
+
+    tokio::spawn(async move {
+        axum::serve(listener, build_router(state)).await.unwrap();
+    });
+
+    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+    // Clients that trust the local CA accept the generated leaf certificate
+    let ca = tokio::fs::read(base.join("certs").join("ca.pem"))
+        .await
+        .unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(&ca).unwrap())
+        .build()
+        .unwrap();
+
+    let response = client
+        .get(format!("https://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.text().await.unwrap(), "<html>secure</html>");
+
+    // Plain HTTP on the TLS port never completes a request
+    let plain = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(2))
+        .build()
+        .unwrap();
+    assert!(plain.get(format!("http://{}/", addr)).send().await.is_err());
+
+    let _ = tokio::fs::remove_dir_all(&base).await;
+}
@@ -0,0 +1,166 @@
+//! Integration tests for W3C Trace Context propagation and OTLP span export
+
+use axum::{
+    Router,
+    body::Bytes,
+    http::HeaderMap,
+    routing::{get, post},
+};
+use local_rs::otlp::Exporter;
+use local_rs::router::build_router;
+use local_rs::routes::ProxyRoute;
+use local_rs::state::AppState;
+use local_rs::trace_context::TraceParent;
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+mod common;
+use common::spawn;
+
+/// Waits for the collector stand-in to receive at least `count` spans
+async fn next_spans(exports: &mut mpsc::UnboundedReceiver<Value>, count: usize) -> Vec<Value> {
+    let mut spans = Vec::new();
+    while spans.len() < count {
+        let export = tokio::time::timeout(Duration::from_secs(5), exports.recv())
+            .await
+            .expect("no spans exported")
+            .unwrap();
+        let resource = &export["resourceSpans"][0];
+        assert_eq!(
+            resource["resource"]["attributes"][0]["value"]["stringValue"],
+            "local-rs"
+        );
+        spans.extend(
+            resource["scopeSpans"][0]["spans"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .cloned(),
+        );
+    }
+    spans
+}
+
+fn attribute<'a>(span: &'a Value, key: &str) -> &'a Value {
+    span["attributes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|attribute| attribute["key"] == key)
+        .map_or(&Value::Null, |attribute| &attribute["value"])
+}
+
+#[tokio::test]
+async fn test_traceparent_propagation_and_export() {
+    // The backend reports the trace headers it received
+    let backend_addr = spawn(Router::new().route(
+        "/pz/trace",
+        get(|headers: HeaderMap| async move {
+            let header = |name| {
+                headers
+                    .get(name)
+                    .map(|value| value.to_str().unwrap().to_string())
+                    .unwrap_or_default()
+            };
+            format!("{}\n{}", header("traceparent"), header("tracestate"))
+        }),
+    ))
+    .await;
+
+    let (received, mut exports) = mpsc::unbounded_channel();
+    let collector_addr = spawn(Router::new().route(
+        "/v1/traces",
+        post(move |body: Bytes| async move {
+            received
+                .send(serde_json::from_slice::<Value>(&body).unwrap())
+                .unwrap();
+        }),
+    ))
+    .await;
+
+    let static_dir = common::test_static_dir().await;
+    let state = Arc::new(AppState {
+        routes: vec![ProxyRoute::new("API", "/pz", &backend_addr)],
+        static_dir,
+        tracer: Some(Exporter::new(&format!("http://{}", collector_addr))),
+        ..Default::default()
+    });
+    let addr = spawn(build_router(state)).await;
+    let client = reqwest::Client::new();
+
+    // A client's trace is continued: same trace ID, a new parent per hop
+    let incoming = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+    let text = client
+        .get(format!("http://{}/pz/trace?x=1", addr))
+        .header("traceparent", incoming)
+        .header("tracestate", "vendor=abc")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let (forwarded, tracestate) = text.split_once('\n').unwrap();
+    let forwarded: TraceParent = forwarded.parse().unwrap();
+    assert_eq!(forwarded.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+    assert_ne!(forwarded.span_id, 0x00f067aa0ba902b7);
+    assert!(forwarded.sampled);
+    assert_eq!(tracestate, "vendor=abc");
+
+    let spans = next_spans(&mut exports, 2).await;
+    let server = spans.iter().find(|span| span["kind"] == 2).unwrap();
+    let upstream = spans.iter().find(|span| span["kind"] == 3).unwrap();
+    assert_eq!(server["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+    assert_eq!(server["parentSpanId"], "00f067aa0ba902b7");
+    assert_eq!(server["name"], "GET /pz/*");
+    assert_eq!(attribute(server, "url.query")["stringValue"], "x=1");
+    assert_eq!(
+        attribute(server, "http.response.status_code")["intValue"],
+        "200"
+    );
+    assert_eq!(upstream["traceId"], server["traceId"]);
+    assert_eq!(upstream["parentSpanId"], server["spanId"]);
+    assert_eq!(upstream["spanId"], format!("{:016x}", forwarded.span_id));
+    assert_eq!(
+        attribute(upstream, "url.full")["stringValue"],
+        format!("http://{}/pz/trace?x=1", backend_addr)
+    );
+
+    // Without a traceparent a new trace starts at local-rs
+    let text = client
+        .get(format!("http://{}/pz/trace", addr))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let (forwarded, tracestate) = text.split_once('\n').unwrap();
+    let forwarded: TraceParent = forwarded.parse().unwrap();
+    assert_ne!(forwarded.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+    assert_eq!(tracestate, "");
+
+    let spans = next_spans(&mut exports, 2).await;
+    let server = spans.iter().find(|span| span["kind"] == 2).unwrap();
+    assert_eq!(server["traceId"], format!("{:032x}", forwarded.trace_id));
+    assert!(server.get("parentSpanId").is_none());
+
+    // Unsampled traces are propagated but not exported
+    let unsampled = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00";
+    let text = client
+        .get(format!("http://{}/pz/trace", addr))
+        .header("traceparent", unsampled)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(text.ends_with("-00\n"), "{}", text);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), exports.recv())
+            .await
+            .is_err()
+    );
+}